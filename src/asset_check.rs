@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+use bevy::{app::AppExit, asset::LoadState, prelude::*};
+
+/// How long the watched assets have to finish loading before giving up.
+/// Loading and decoding the san miguel textures can take a while on slow disks.
+const ASSET_LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Assets the scene can't be shown without, with the paths they were loaded from.
+/// If one of them fails or doesn't load within [`ASSET_LOAD_TIMEOUT`] the app exits.
+#[derive(Resource)]
+pub struct WatchedAssets {
+    assets: Vec<(UntypedHandle, String)>,
+    started: Instant,
+}
+
+impl WatchedAssets {
+    pub fn new() -> Self {
+        Self {
+            assets: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, handle: impl Into<UntypedHandle>, path: impl Into<String>) {
+        self.assets.push((handle.into(), path.into()));
+    }
+}
+
+pub fn check_watched_assets(
+    mut commands: Commands,
+    watched: Res<WatchedAssets>,
+    asset_server: Res<AssetServer>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    let mut loading = Vec::new();
+    for (handle, path) in &watched.assets {
+        match asset_server.load_state(handle.id()) {
+            LoadState::Loaded => (),
+            LoadState::Failed => {
+                error!("Failed to load {path}, check that it exists in the assets directory");
+                app_exit.send(AppExit);
+                return;
+            }
+            LoadState::NotLoaded | LoadState::Loading => loading.push(path),
+        }
+    }
+    if loading.is_empty() {
+        commands.remove_resource::<WatchedAssets>();
+    } else if watched.started.elapsed() > ASSET_LOAD_TIMEOUT {
+        for path in loading {
+            error!(
+                "{path} didn't load within {}s, check that it and its textures exist in the assets directory",
+                ASSET_LOAD_TIMEOUT.as_secs()
+            );
+        }
+        app_exit.send(AppExit);
+    }
+}
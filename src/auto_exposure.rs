@@ -0,0 +1,281 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::core_3d::graph::{Core3d, Node3d},
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{storage_buffer, texture_2d},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer, BufferDescriptor,
+            BufferUsages, CachedComputePipelineId, CommandEncoderDescriptor, ComputePassDescriptor,
+            ComputePipelineDescriptor, Maintain, MapMode, PipelineCache, ShaderStages,
+            TextureSampleType,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::{ColorGrading, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{exposure_mut, time_running};
+
+const AUTO_EXPOSURE_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(30571948264038215);
+
+// Average luminance the exposure brings the image to
+const MIDDLE_GRAY: f32 = 0.18;
+
+// The measured average is a single f32
+const LUMINANCE_SIZE: u64 = std::mem::size_of::<f32>() as u64;
+
+/// Moves the camera's exposure towards the one that brings the average luminance of its hdr
+/// image to middle gray, like eyes adapting when walking from the bright courtyard into the dark
+/// arcade. `speed` is how quickly, per second, it closes the gap. The exposure stays between
+/// `min` and `max` stops. Changing the exposure by hand removes it from the camera.
+#[derive(Component, Clone, Copy, ExtractComponent)]
+pub struct AutoExposure {
+    pub speed: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// The log2 of the average luminance last measured on the gpu, `None` until the first
+/// measurement is read back. Shared between the main and render world.
+#[derive(Resource, Clone, Default)]
+struct MeasuredLuminance(Arc<Mutex<Option<f32>>>);
+
+/// With --auto-exposure, measures the image of the camera that has [`AutoExposure`] every frame
+/// and adjusts its exposure
+pub struct AutoExposurePlugin;
+
+impl Plugin for AutoExposurePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            AUTO_EXPOSURE_SHADER_HANDLE,
+            "auto_exposure.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins(ExtractComponentPlugin::<AutoExposure>::default())
+            .init_resource::<MeasuredLuminance>()
+            .add_systems(Update, adapt_exposure.run_if(time_running));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let measured = app.world.resource::<MeasuredLuminance>().clone();
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        let render_device = render_app.world.resource::<RenderDevice>().clone();
+        render_app
+            .insert_resource(LuminanceReadback::new(&render_device, measured))
+            .init_resource::<AutoExposurePipeline>()
+            .add_systems(
+                Render,
+                read_back_luminance
+                    .after(RenderSet::Render)
+                    .before(RenderSet::Cleanup),
+            )
+            .add_render_graph_node::<ViewNodeRunner<AutoExposureNode>>(Core3d, AutoExposureLabel)
+            // Measured before tonemapping, where the exposure is applied
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::EndMainPass, AutoExposureLabel, Node3d::Tonemapping),
+            );
+    }
+}
+
+fn adapt_exposure(
+    time: Res<Time>,
+    measured: Res<MeasuredLuminance>,
+    mut cameras: Query<(&AutoExposure, &mut ColorGrading)>,
+) {
+    let Some(log_luminance) = *measured.0.lock().unwrap() else {
+        return;
+    };
+    for (auto_exposure, mut color_grading) in &mut cameras {
+        let target =
+            (MIDDLE_GRAY.log2() - log_luminance).clamp(auto_exposure.min, auto_exposure.max);
+        let exposure = exposure_mut(&mut color_grading);
+        // Frame rate independent exponential approach
+        *exposure +=
+            (target - *exposure) * (1.0 - (-auto_exposure.speed * time.delta_seconds()).exp());
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct AutoExposureLabel;
+
+#[derive(Default)]
+struct AutoExposureNode;
+
+impl ViewNode for AutoExposureNode {
+    type ViewQuery = (&'static ViewTarget, &'static AutoExposure);
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, _): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let exposure_pipeline = world.resource::<AutoExposurePipeline>();
+        let readback = world.resource::<LuminanceReadback>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(exposure_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "auto_exposure_bind_group",
+            &exposure_pipeline.layout,
+            &BindGroupEntries::sequential((
+                view_target.main_texture_view(),
+                readback.luminance_buffer.as_entire_binding(),
+            )),
+        );
+        let mut pass =
+            render_context
+                .command_encoder()
+                .begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("auto_exposure_pass"),
+                    timestamp_writes: None,
+                });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+        readback.written.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct LuminanceReadback {
+    luminance_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Whether the node measured the image this frame
+    written: AtomicBool,
+    /// Whether `readback_buffer` is waiting to be mapped, it can't be copied to until it's read.
+    mapping: bool,
+    mapped: Arc<AtomicBool>,
+    measured: MeasuredLuminance,
+}
+
+impl LuminanceReadback {
+    fn new(render_device: &RenderDevice, measured: MeasuredLuminance) -> Self {
+        Self {
+            luminance_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("auto_exposure_luminance_buffer"),
+                size: LUMINANCE_SIZE,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("auto_exposure_readback_buffer"),
+                size: LUMINANCE_SIZE,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            written: AtomicBool::new(false),
+            mapping: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+            measured,
+        }
+    }
+}
+
+/// Copies this frame's measurement to the readback buffer if the last one was read already.
+/// The buffer is mapped asynchronously so this never waits on the gpu, the exposure lags the
+/// image by a few frames.
+fn read_back_luminance(
+    mut readback: ResMut<LuminanceReadback>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    render_device.poll(Maintain::Poll);
+    if readback.mapping {
+        if !readback.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let log_luminance = {
+            let data = readback.readback_buffer.slice(..).get_mapped_range();
+            f32::from_le_bytes(data[..].try_into().unwrap())
+        };
+        readback.readback_buffer.unmap();
+        readback.mapping = false;
+        if log_luminance.is_finite() {
+            *readback.measured.0.lock().unwrap() = Some(log_luminance);
+        }
+    }
+    if !readback.written.swap(false, Ordering::Acquire) {
+        return;
+    }
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("auto_exposure_readback"),
+    });
+    encoder.copy_buffer_to_buffer(
+        &readback.luminance_buffer,
+        0,
+        &readback.readback_buffer,
+        0,
+        LUMINANCE_SIZE,
+    );
+    render_queue.submit([encoder.finish()]);
+
+    let mapped = readback.mapped.clone();
+    render_device.map_buffer(
+        &readback.readback_buffer.slice(..),
+        MapMode::Read,
+        move |result| match result {
+            Ok(()) => mapped.store(true, Ordering::Release),
+            Err(e) => error!("Failed to read back the measured luminance: {e}"),
+        },
+    );
+    readback.mapping = true;
+}
+
+#[derive(Resource)]
+struct AutoExposurePipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedComputePipelineId,
+}
+
+impl FromWorld for AutoExposurePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let layout = world.resource::<RenderDevice>().create_bind_group_layout(
+            "auto_exposure_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    storage_buffer::<f32>(false),
+                ),
+            ),
+        );
+        let pipeline_id = world
+            .resource_mut::<PipelineCache>()
+            .queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("auto_exposure_pipeline".into()),
+                layout: vec![layout.clone()],
+                push_constant_ranges: vec![],
+                shader: AUTO_EXPOSURE_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "measure".into(),
+            });
+        Self {
+            layout,
+            pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> average_log_luminance: f32;
+
+// Each invocation samples a 4x4 block of a 64x64 grid spread over the image
+const GRID_SIZE: u32 = 64u;
+const BLOCK_SIZE: u32 = 4u;
+const INVOCATIONS: u32 = 256u;
+
+var<workgroup> sums: array<f32, INVOCATIONS>;
+
+@compute @workgroup_size(16, 16, 1)
+fn measure(
+    @builtin(local_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    let size = vec2<f32>(textureDimensions(screen_texture));
+    var sum = 0.0;
+    for (var y = 0u; y < BLOCK_SIZE; y++) {
+        for (var x = 0u; x < BLOCK_SIZE; x++) {
+            let cell = id.xy * BLOCK_SIZE + vec2(x, y);
+            let coords = (vec2<f32>(cell) + 0.5) / f32(GRID_SIZE) * size;
+            let color = textureLoad(screen_texture, vec2<u32>(coords), 0).rgb;
+            let luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+            // Keeps black pixels and the odd inf from dominating the average
+            sum += clamp(log2(max(luminance, 1e-5)), -16.0, 16.0);
+        }
+    }
+    sums[index] = sum;
+    workgroupBarrier();
+
+    for (var stride = INVOCATIONS / 2u; stride > 0u; stride /= 2u) {
+        if index < stride {
+            sums[index] += sums[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if index == 0u {
+        average_log_luminance = sums[0] / f32(GRID_SIZE * GRID_SIZE);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+/// Compares the frame times of two `--bench-json` results and prints the change of each metric,
+/// marking the ones that got more than `threshold` percent slower or faster. Returns whether any
+/// regressed. Differences in the settings they were run with are printed first.
+pub fn compare_bench_json(baseline: &Path, new: &Path, threshold: f32) -> anyhow::Result<bool> {
+    let read = |path: &Path| -> anyhow::Result<Value> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    };
+    let (a, b) = (read(baseline)?, read(new)?);
+    if a["schema"] != b["schema"] {
+        bail!(
+            "the files have different schemas, {} and {}",
+            a["schema"],
+            b["schema"]
+        );
+    }
+
+    println!("Comparing {} -> {}", baseline.display(), new.display());
+    if let (Some(a_settings), Some(b_settings)) =
+        (a["settings"].as_object(), b["settings"].as_object())
+    {
+        for (name, a_value) in a_settings {
+            let b_value = b_settings.get(name).unwrap_or(&Value::Null);
+            if a_value != b_value {
+                println!("Settings differ, {name}: {a_value} -> {b_value}");
+            }
+        }
+    }
+
+    let mut metrics = Vec::new();
+    for stat in ["avg", "p50", "p95", "p99", "max"] {
+        metrics.push((
+            format!("cpu {stat}"),
+            &a["cpu_frame_time_ms"][stat],
+            &b["cpu_frame_time_ms"][stat],
+        ));
+    }
+    let positions = |result: &Value| {
+        result["position_cpu_frame_time_ms"]
+            .as_array()
+            .map_or(0, Vec::len)
+    };
+    if positions(&a) != positions(&b) {
+        println!(
+            "The runs have {} and {} camera positions, they aren't compared",
+            positions(&a),
+            positions(&b)
+        );
+    } else {
+        for i in 0..positions(&a) {
+            metrics.push((
+                format!("position {}", i + 1),
+                &a["position_cpu_frame_time_ms"][i],
+                &b["position_cpu_frame_time_ms"][i],
+            ));
+        }
+    }
+    // Only with --gpu-timing on both runs
+    if let (Some(a_passes), Some(b_passes)) = (
+        a["gpu_pass_time_ms"].as_object(),
+        b["gpu_pass_time_ms"].as_object(),
+    ) {
+        for (pass, a_time) in a_passes {
+            if let Some(b_time) = b_passes.get(pass) {
+                metrics.push((format!("gpu {pass}"), a_time, b_time));
+            }
+        }
+    }
+
+    println!(
+        "{:>16} {:>10} {:>10} {:>8}",
+        "metric", "baseline", "new", "change"
+    );
+    let mut regressed = false;
+    for (name, a_ms, b_ms) in metrics {
+        let (Some(a_ms), Some(b_ms)) = (a_ms.as_f64(), b_ms.as_f64()) else {
+            continue;
+        };
+        let change = if a_ms > 0.0 {
+            (b_ms / a_ms - 1.0) * 100.0
+        } else {
+            0.0
+        };
+        let marker = if change > threshold as f64 {
+            regressed = true;
+            "regression"
+        } else if change < -threshold as f64 {
+            "improvement"
+        } else {
+            ""
+        };
+        println!("{name:>16} {a_ms:>8.2}ms {b_ms:>8.2}ms {change:>+7.1}% {marker}");
+    }
+    Ok(regressed)
+}
@@ -0,0 +1,350 @@
+use bevy::{
+    pbr::{
+        CascadeShadowConfig, DirectionalLightShadowMap, ScreenSpaceAmbientOcclusionQualityLevel,
+        ScreenSpaceAmbientOcclusionSettings,
+    },
+    prelude::*,
+    render::camera::RenderTarget,
+    window::{PrimaryWindow, WindowResolution},
+};
+
+use crate::{
+    headless_render_target, minimap::MinimapCamera, parse_ssao_quality, sun_cascades, Args,
+    GrifLight, SunLight, MAX_SHADOW_MAP_SIZE,
+};
+
+/// Frames rendered after applying a swept value before its benchmark run starts,
+/// so the new shadow maps and pipelines are ready
+const SWEEP_SETTLE_FRAMES: u32 = 30;
+
+/// The settings --bench-sweep can change between runs
+const SWEEPABLE_SETTINGS: [&str; 5] = [
+    "shadow-cascades",
+    "shadow-distance",
+    "ssao-quality",
+    "resolution",
+    "spot-shadows",
+];
+
+#[derive(Clone, Copy)]
+pub enum SweepValue {
+    ShadowCascades(u8),
+    ShadowDistance(f32),
+    SsaoQuality(ScreenSpaceAmbientOcclusionQualityLevel),
+    Resolution(u32, u32),
+    /// The shadow map size is only changed if one was given
+    SpotShadows {
+        enabled: bool,
+        map_size: Option<u32>,
+    },
+}
+
+/// A setting from [`SWEEPABLE_SETTINGS`] and the values to benchmark it with, as given and parsed
+#[derive(Clone)]
+pub struct SweepSpec {
+    pub setting: String,
+    pub values: Vec<(String, SweepValue)>,
+}
+
+pub fn parse_bench_sweep(spec: &str) -> Result<SweepSpec, String> {
+    let Some((setting, values)) = spec.split_once('=') else {
+        return Err(format!("expected <setting>=<value>,<value>..., got {spec}"));
+    };
+    let setting = setting.trim().to_lowercase();
+    let parse_value = |value: &str| -> Result<SweepValue, String> {
+        let invalid = |e: String| format!("invalid {setting} value {value}: {e}");
+        match setting.as_str() {
+            "shadow-cascades" => match value.parse::<u8>() {
+                Ok(cascades) if (1..=4).contains(&cascades) => {
+                    Ok(SweepValue::ShadowCascades(cascades))
+                }
+                _ => Err(invalid("expected 1 to 4".into())),
+            },
+            "shadow-distance" => match value.parse::<f32>() {
+                Ok(distance) if distance > 0.0 && distance.is_finite() => {
+                    Ok(SweepValue::ShadowDistance(distance))
+                }
+                _ => Err(invalid("expected a positive number".into())),
+            },
+            "ssao-quality" => parse_ssao_quality(value)
+                .map(SweepValue::SsaoQuality)
+                .map_err(invalid),
+            "resolution" => value
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                .filter(|(width, height)| *width > 0 && *height > 0)
+                .map(|(width, height)| SweepValue::Resolution(width, height))
+                .ok_or_else(|| invalid("expected <width>x<height>".into())),
+            "spot-shadows" => match value {
+                "off" | "on" => Ok(SweepValue::SpotShadows {
+                    enabled: value == "on",
+                    map_size: None,
+                }),
+                _ => match value.parse::<u32>() {
+                    Ok(size) if (1..=MAX_SHADOW_MAP_SIZE).contains(&size) => {
+                        Ok(SweepValue::SpotShadows {
+                            enabled: true,
+                            map_size: Some(size),
+                        })
+                    }
+                    _ => Err(invalid(format!(
+                        "expected off, on or a shadow map size up to {MAX_SHADOW_MAP_SIZE}"
+                    ))),
+                },
+            },
+            _ => Err(format!(
+                "can't sweep {setting}, expected one of {}",
+                SWEEPABLE_SETTINGS.join(", ")
+            )),
+        }
+    };
+    let values = values
+        .split(',')
+        .map(str::trim)
+        .map(|value| Ok((value.to_string(), parse_value(value)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(SweepSpec { setting, values })
+}
+
+/// With --bench-sweep, the benchmark runs once for each value, which [`apply_bench_sweep`] sets
+/// before the run starts
+#[derive(Resource)]
+pub struct BenchSweep {
+    spec: SweepSpec,
+    /// Index of the value being benchmarked
+    index: usize,
+    /// Set when a run should start once the current value is applied and has settled
+    waiting: bool,
+    /// Frames since the current value was applied, None until it is
+    applied_frames: Option<u32>,
+    /// Average cpu frame time of each finished value, in seconds
+    results: Vec<f32>,
+    /// The settings before the first value was applied, set back once every value has run
+    original: Option<SweptSettings>,
+    /// Set when the sweep is done and [`apply_bench_sweep`] should restore `original`
+    restore: bool,
+}
+
+/// Everything a swept value can change, as it was before the sweep
+struct SweptSettings {
+    cascades: Vec<(Entity, CascadeShadowConfig)>,
+    ssao_quality: Vec<(Entity, ScreenSpaceAmbientOcclusionQualityLevel)>,
+    window_resolution: Vec<(Entity, WindowResolution)>,
+    spot_shadows: Vec<(Entity, bool)>,
+    shadow_map_size: usize,
+}
+
+impl BenchSweep {
+    pub fn new(spec: SweepSpec) -> Self {
+        Self {
+            spec,
+            index: 0,
+            waiting: false,
+            applied_frames: None,
+            results: Vec::new(),
+            original: None,
+            restore: false,
+        }
+    }
+
+    /// Called when a benchmark would start, returns true once the value is applied and settled
+    pub fn ready_to_start(&mut self) -> bool {
+        self.waiting = true;
+        let Some(frames) = &mut self.applied_frames else {
+            return false;
+        };
+        *frames += 1;
+        if *frames < SWEEP_SETTLE_FRAMES {
+            return false;
+        }
+        self.waiting = false;
+        true
+    }
+
+    /// Whether a run still has to be started, so a benchmark that would start can't be missed
+    pub fn waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Records a finished run, returns false once every value has been benchmarked
+    pub fn finish_run(&mut self, avg_frame_time: f32) -> bool {
+        self.results.push(avg_frame_time);
+        self.index += 1;
+        self.applied_frames = None;
+        if self.index < self.spec.values.len() {
+            self.waiting = true;
+            return true;
+        }
+        self.print();
+        // Starting another benchmark sweeps again
+        self.index = 0;
+        self.results.clear();
+        self.restore = true;
+        false
+    }
+
+    fn print(&self) {
+        println!("Benchmark sweep of {}:", self.spec.setting);
+        println!("{:>12}  avg cpu frame time", self.spec.setting);
+        for ((label, _), frame_time) in self.spec.values.iter().zip(&self.results) {
+            println!("{label:>12}  {:.2}ms", frame_time * 1000.0);
+        }
+    }
+}
+
+/// Applies the swept value the next benchmark run uses, and puts the settings back the way they
+/// were once the sweep is done
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn apply_bench_sweep(
+    mut sweep: ResMut<BenchSweep>,
+    args: Res<Args>,
+    mut sun: Query<(Entity, &mut CascadeShadowConfig), With<SunLight>>,
+    mut camera: Query<
+        (
+            Entity,
+            &Camera,
+            Option<&mut ScreenSpaceAmbientOcclusionSettings>,
+        ),
+        Without<MinimapCamera>,
+    >,
+    mut window: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+    mut images: ResMut<Assets<Image>>,
+    mut spot_lights: Query<(Entity, &mut SpotLight), With<GrifLight>>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+) {
+    if sweep.restore {
+        sweep.restore = false;
+        let Some(original) = sweep.original.take() else {
+            return;
+        };
+        println!("Benchmark sweep done, restoring {}", sweep.spec.setting);
+        // Only what the swept setting changes, the rest may have been changed by hand since
+        match sweep.spec.values[0].1 {
+            SweepValue::ShadowCascades(_) | SweepValue::ShadowDistance(_) => {
+                for (entity, config) in original.cascades {
+                    if let Ok((_, mut current)) = sun.get_mut(entity) {
+                        *current = config;
+                    }
+                }
+            }
+            SweepValue::SsaoQuality(_) => {
+                for (entity, quality_level) in original.ssao_quality {
+                    if let Ok((_, _, Some(mut ssao))) = camera.get_mut(entity) {
+                        ssao.quality_level = quality_level;
+                    }
+                }
+            }
+            SweepValue::Resolution(..) => {
+                let (width, height) = args.resolution();
+                set_render_target_size(&camera, &mut images, width, height);
+                for (entity, resolution) in original.window_resolution {
+                    if let Ok((_, mut window)) = window.get_mut(entity) {
+                        window.resolution = resolution;
+                    }
+                }
+            }
+            SweepValue::SpotShadows { .. } => {
+                for (entity, enabled) in original.spot_shadows {
+                    if let Ok((_, mut light)) = spot_lights.get_mut(entity) {
+                        light.shadows_enabled = enabled;
+                    }
+                }
+                shadow_map.size = original.shadow_map_size;
+            }
+        }
+        return;
+    }
+    if !sweep.waiting || sweep.applied_frames.is_some() {
+        return;
+    }
+    if sweep.original.is_none() {
+        sweep.original = Some(SweptSettings {
+            cascades: sun
+                .iter()
+                .map(|(entity, config)| (entity, config.clone()))
+                .collect(),
+            ssao_quality: camera
+                .iter()
+                .filter_map(|(entity, _, ssao)| Some((entity, ssao?.quality_level)))
+                .collect(),
+            window_resolution: window
+                .iter()
+                .map(|(entity, window)| (entity, window.resolution.clone()))
+                .collect(),
+            spot_shadows: spot_lights
+                .iter()
+                .map(|(entity, light)| (entity, light.shadows_enabled))
+                .collect(),
+            shadow_map_size: shadow_map.size,
+        });
+    }
+    let (label, value) = sweep.spec.values[sweep.index].clone();
+    println!(
+        "Benchmark sweep {}/{}: {} = {label}",
+        sweep.index + 1,
+        sweep.spec.values.len(),
+        sweep.spec.setting
+    );
+    match value {
+        SweepValue::ShadowCascades(cascades) => {
+            for (_, mut config) in &mut sun {
+                *config = sun_cascades(cascades, args.shadow_distance);
+            }
+        }
+        SweepValue::ShadowDistance(distance) => {
+            for (_, mut config) in &mut sun {
+                *config = sun_cascades(args.shadow_cascades, distance);
+            }
+        }
+        SweepValue::SsaoQuality(quality_level) => {
+            for (_, _, ssao) in &mut camera {
+                match ssao {
+                    Some(mut ssao) => ssao.quality_level = quality_level,
+                    None => warn!("SSAO is off, sweeping its quality won't change anything"),
+                }
+            }
+        }
+        SweepValue::Resolution(width, height) => {
+            set_render_target_size(&camera, &mut images, width, height);
+            for (_, mut window) in &mut window {
+                window.resolution.set(width as f32, height as f32);
+            }
+        }
+        SweepValue::SpotShadows { enabled, map_size } => {
+            if spot_lights.is_empty() {
+                warn!("There are no spot lights, sweeping their shadows won't change anything");
+            }
+            for (_, mut light) in &mut spot_lights {
+                light.shadows_enabled = enabled;
+            }
+            // Shared with the sun cascades, values without a size use the one from before
+            let original_size = sweep.original.as_ref().unwrap().shadow_map_size;
+            shadow_map.size = map_size.map_or(original_size, |size| size as usize);
+        }
+    }
+    sweep.applied_frames = Some(0);
+}
+
+/// Without a window the camera renders into an image the size of the resolution
+#[allow(clippy::type_complexity)]
+fn set_render_target_size(
+    camera: &Query<
+        (
+            Entity,
+            &Camera,
+            Option<&mut ScreenSpaceAmbientOcclusionSettings>,
+        ),
+        Without<MinimapCamera>,
+    >,
+    images: &mut Assets<Image>,
+    width: u32,
+    height: u32,
+) {
+    for (_, camera, _) in camera {
+        if let RenderTarget::Image(target) = &camera.target {
+            if let Some(image) = images.get_mut(target) {
+                *image = headless_render_target(width, height);
+            }
+        }
+    }
+}
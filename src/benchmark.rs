@@ -0,0 +1,545 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+use bevy::{
+    app::AppExit, ecs::system::SystemParam, prelude::*, render::view::VisibleEntities,
+    time::TimeUpdateStrategy, window::PresentMode,
+};
+
+use crate::{
+    bench_sweep::BenchSweep,
+    camera_controller::CameraController,
+    camera_positions::{move_camera_to, CameraPositions, CameraTween},
+    gpu_timing::{GpuTimings, GPU_PASSES},
+    key_bindings::KeyBindings,
+    minimap::MinimapCamera,
+    mipmap_generator::MipmapTasks,
+    scene_bounds::SceneRoot,
+    screenshot::CaptureScreenshot,
+    Args, PostProcScene, SSAO_QUALITY_LEVELS,
+};
+
+/// Frames the scene has to be fully loaded for before the headless benchmark or a flythrough starts
+pub const SCENE_SETTLE_FRAMES: u32 = 60;
+// Frames to keep running after a headless benchmark so the last screenshot gets rendered and saved
+const HEADLESS_EXIT_FRAMES: u32 = 10;
+// Frames rendered at each camera position with --prewarm, enough for the pipelines to compile
+const PREWARM_FRAMES: u32 = 30;
+// Simulated frame time with --bench-fixed-frames, so tweens and animations advance the same every run
+const FIXED_FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Version of the --bench-json layout
+const BENCH_JSON_SCHEMA: u32 = 1;
+
+/// The segment columns depend on the number of camera positions
+fn csv_header(segments: usize) -> String {
+    let segments = (1..=segments)
+        .map(|i| format!("segment_{i}_ms,"))
+        .collect::<String>();
+    format!("timestamp,commit,{segments}avg_ms,mesh_entities,meshes,materials")
+}
+
+/// Whether the scene has been processed and its mipmaps generated
+#[derive(SystemParam)]
+pub struct SceneLoadState<'w, 's> {
+    loading_scenes: Query<'w, 's, (), With<PostProcScene>>,
+    mipmap_tasks: Option<Res<'w, MipmapTasks<StandardMaterial>>>,
+    mesh_entities: Query<'w, 's, (), With<Handle<Mesh>>>,
+}
+
+impl SceneLoadState<'_, '_> {
+    pub fn is_loaded(&self) -> bool {
+        self.is_processed() && self.mipmaps_generated()
+    }
+
+    /// Whether [`crate::proc_scene`] has run on every spawned scene
+    pub fn is_processed(&self) -> bool {
+        self.loading_scenes.is_empty() && !self.mesh_entities.is_empty()
+    }
+
+    /// Whether no mipmap generation task is left running
+    pub fn mipmaps_generated(&self) -> bool {
+        self.mipmap_tasks
+            .as_ref()
+            .is_some_and(|tasks| tasks.is_empty())
+    }
+
+    pub fn mesh_entities(&self) -> usize {
+        self.mesh_entities.iter().count()
+    }
+
+    /// Mesh entities that passed the view's frustum culling last frame, all of them with
+    /// --no-frustum-culling
+    pub fn rendered_meshes(&self, visible: &VisibleEntities) -> usize {
+        visible
+            .iter()
+            .filter(|entity| self.mesh_entities.contains(**entity))
+            .count()
+    }
+}
+
+/// How much of the scene is loaded, for the benchmark results
+#[derive(SystemParam)]
+pub struct SceneCounts<'w, 's> {
+    scene_roots: Query<'w, 's, (), With<SceneRoot>>,
+    meshes: Res<'w, Assets<Mesh>>,
+    materials: Res<'w, Assets<StandardMaterial>>,
+}
+
+/// Whether a benchmark run is in progress
+#[derive(Resource, Default)]
+pub struct BenchmarkRunning(pub bool);
+
+#[derive(Default)]
+pub struct BenchmarkState {
+    started: Option<Instant>,
+    segment_started: Option<Instant>,
+    frame: u32,
+    count_per_step: u32,
+    settled_frames: u32,
+    exit_countdown: Option<u32>,
+    /// Frame of the --prewarm pass through the camera positions, while it runs
+    prewarm_frame: Option<u32>,
+    prewarmed: bool,
+    /// Set while waiting for a [`CameraTween`] to finish, that time isn't counted
+    tween_started: Option<Instant>,
+    /// Average cpu frame time of each finished camera position, in seconds
+    segment_frame_times: Vec<f32>,
+    /// Meshes rendered at each finished camera position
+    segment_rendered_meshes: Vec<usize>,
+    /// Every counted frame's real delta time since the run started, in seconds
+    frame_times: Vec<f32>,
+    /// When the last frame of the run was counted. `Time<Real>` can't be used, with
+    /// --bench-fixed-frames it advances by the fixed step too.
+    last_frame: Option<Instant>,
+}
+
+pub struct BenchmarkResult {
+    /// Average cpu frame time of each camera position, in seconds
+    pub segment_frame_times: Vec<f32>,
+    /// Meshes the main view rendered at each camera position after culling, counted on its last
+    /// frame
+    pub segment_rendered_meshes: Vec<usize>,
+    /// Average cpu frame time over the whole run, in seconds
+    pub avg_frame_time: f32,
+    pub p50_frame_time: f32,
+    pub p95_frame_time: f32,
+    pub p99_frame_time: f32,
+    pub max_frame_time: f32,
+    /// Average gpu time of each of [`GPU_PASSES`] in milliseconds, with --gpu-timing
+    pub gpu_pass_times: Option<[f32; GPU_PASSES.len()]>,
+    pub scene_instances: usize,
+    pub mesh_entities: usize,
+    pub meshes: usize,
+    pub materials: usize,
+}
+
+impl BenchmarkResult {
+    pub fn print(&self) {
+        println!(
+            "Benchmark avg cpu frame time: {:.2}ms",
+            self.avg_frame_time * 1000.0
+        );
+        if let Some(pass_times) = self.gpu_pass_times {
+            let passes = GPU_PASSES
+                .iter()
+                .zip(pass_times)
+                .map(|(name, time)| format!("{name} {time:.2}ms"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!(
+                "Benchmark avg gpu frame time: {:.2}ms ({passes})",
+                pass_times.iter().sum::<f32>()
+            );
+        }
+        for (i, (frame_time, rendered_meshes)) in self
+            .segment_frame_times
+            .iter()
+            .zip(&self.segment_rendered_meshes)
+            .enumerate()
+        {
+            println!(
+                "Benchmark position {} avg cpu frame time: {:.2}ms, {rendered_meshes} meshes rendered",
+                i + 1,
+                frame_time * 1000.0
+            );
+        }
+        for (name, frame_time) in [
+            ("p50", self.p50_frame_time),
+            ("p95", self.p95_frame_time),
+            ("p99", self.p99_frame_time),
+            ("max", self.max_frame_time),
+        ] {
+            println!(
+                "Benchmark {name} cpu frame time: {:.2}ms",
+                frame_time * 1000.0
+            );
+        }
+        println!(
+            "Benchmark scene instances: {}, mesh entities: {}, meshes: {}, materials: {}",
+            self.scene_instances, self.mesh_entities, self.meshes, self.materials
+        );
+    }
+
+    /// Appends a row to the csv at `path`, writing the header first if the file is new.
+    /// Errors if the file was written for a different number of camera positions.
+    /// The commit is taken from the `GIT_COMMIT` env var if it is set.
+    pub fn append_csv(&self, path: &Path) -> std::io::Result<()> {
+        let header = csv_header(self.segment_frame_times.len());
+        let write_header = !path.exists();
+        if !write_header {
+            let existing = fs::read_to_string(path)?;
+            if existing.lines().next().is_some_and(|line| line != header) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "it has different columns, were the camera positions changed?",
+                ));
+            }
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if write_header {
+            writeln!(file, "{header}")?;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let commit = std::env::var("GIT_COMMIT").unwrap_or_default();
+        let segments = self
+            .segment_frame_times
+            .iter()
+            .map(|t| format!("{:.3}", t * 1000.0))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            file,
+            "{timestamp},{commit},{segments},{:.3},{},{},{}",
+            self.avg_frame_time * 1000.0,
+            self.mesh_entities,
+            self.meshes,
+            self.materials
+        )
+    }
+
+    /// Writes the result and the settings it was run with to `path` as one json object.
+    /// Bump [`BENCH_JSON_SCHEMA`] when changing the layout.
+    pub fn write_json(&self, path: &Path, args: &Args) -> anyhow::Result<()> {
+        let ms = |seconds: f32| seconds * 1000.0;
+        let (width, height) = args.resolution();
+        let gpu_pass_times = self.gpu_pass_times.map(|pass_times| {
+            GPU_PASSES
+                .iter()
+                .zip(pass_times)
+                .map(|(name, time)| (name.to_string(), json!(time)))
+                .collect::<serde_json::Map<_, _>>()
+        });
+        let json = json!({
+            "schema": BENCH_JSON_SCHEMA,
+            "timestamp": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            "commit": std::env::var("GIT_COMMIT").unwrap_or_default(),
+            "settings": {
+                "scene": args.scene,
+                "width": width,
+                "height": height,
+                "minimal": args.minimal,
+                "hdr": !args.no_hdr,
+                "instance": args.instance,
+                "aa": format!("{:?}", args.aa),
+                "ssao_quality": SSAO_QUALITY_LEVELS
+                    .iter()
+                    .find(|(_, quality)| *quality == args.ssao_quality)
+                    .map(|(name, _)| name),
+                "compression_format": args.compression_format.map(|format| format.to_string()),
+                "compression_speed": format!("{:?}", args.compression_speed),
+                "ktx2_zstd": !args.no_zstd,
+                "max_texture_size": args.max_texture_size,
+                "fixed_frames": args.bench_fixed_frames,
+                "warmup_frames": args.bench_warmup,
+                "spot_shadows": args.spot_shadows,
+                "spot_shadow_map_size": args.spot_shadow_map_size,
+            },
+            "cpu_frame_time_ms": {
+                "avg": ms(self.avg_frame_time),
+                "p50": ms(self.p50_frame_time),
+                "p95": ms(self.p95_frame_time),
+                "p99": ms(self.p99_frame_time),
+                "max": ms(self.max_frame_time),
+            },
+            "position_cpu_frame_time_ms": self
+                .segment_frame_times
+                .iter()
+                .map(|t| ms(*t))
+                .collect::<Vec<_>>(),
+            "position_rendered_meshes": self.segment_rendered_meshes,
+            "gpu_pass_time_ms": gpu_pass_times,
+            "scene_instances": self.scene_instances,
+            "mesh_entities": self.mesh_entities,
+            "meshes": self.meshes,
+            "materials": self.materials,
+        });
+        fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of already sorted values, `p` in 0..=100
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn benchmark(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut camera: Query<
+        (
+            Entity,
+            &mut Transform,
+            Has<CameraTween>,
+            Option<&mut CameraController>,
+            &VisibleEntities,
+        ),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut state: Local<BenchmarkState>,
+    time: Res<Time>,
+    args: Res<Args>,
+    scene: SceneLoadState,
+    counts: SceneCounts,
+    mut screenshots: EventWriter<CaptureScreenshot>,
+    mut app_exit: EventWriter<AppExit>,
+    gpu_timings: Option<Res<GpuTimings>>,
+    mut running: ResMut<BenchmarkRunning>,
+    camera_positions: Res<CameraPositions>,
+    mut sweep: Option<ResMut<BenchSweep>>,
+) {
+    if let Some(countdown) = &mut state.exit_countdown {
+        if *countdown == 0 {
+            app_exit.send(AppExit);
+        }
+        *countdown = countdown.saturating_sub(1);
+        return;
+    }
+    let mut start = input.just_pressed(keys.benchmark);
+    if args.bench_headless && state.started.is_none() {
+        state.settled_frames = if scene.is_loaded() {
+            state.settled_frames + 1
+        } else {
+            0
+        };
+        start = state.settled_frames == SCENE_SETTLE_FRAMES;
+    }
+    if start && state.started.is_none() && args.prewarm && !state.prewarmed {
+        let Ok((entity, _, _, controller, _)) = camera.get_single_mut() else {
+            return;
+        };
+        if let Some(mut controller) = controller {
+            controller.enabled = false;
+        }
+        println!(
+            "Prewarming shaders at {} positions before the benchmark",
+            camera_positions.len()
+        );
+        commands.entity(entity).remove::<CameraTween>();
+        running.0 = true;
+        state.prewarm_frame = Some(0);
+        state.prewarmed = true;
+        start = false;
+    }
+    if let Some(frame) = state.prewarm_frame {
+        let Ok((entity, mut transform, ..)) = camera.get_single_mut() else {
+            return;
+        };
+        // Frames rendered here only make sure every pipeline has compiled, none are counted
+        let position = (frame / PREWARM_FRAMES) as usize;
+        if position < camera_positions.len() {
+            if frame.is_multiple_of(PREWARM_FRAMES) {
+                move_camera_to(
+                    &mut commands,
+                    entity,
+                    &mut transform,
+                    camera_positions[position],
+                    None,
+                );
+            }
+            state.prewarm_frame = Some(frame + 1);
+            return;
+        }
+        state.prewarm_frame = None;
+        start = true;
+    }
+    // With --bench-sweep each run waits for its value to be applied
+    if let Some(sweep) = &mut sweep {
+        if state.started.is_none() && (start || sweep.waiting()) {
+            start = sweep.ready_to_start();
+        }
+    }
+    if start && state.started.is_none() {
+        running.0 = true;
+        // Moving the camera would change what is measured
+        if let Ok((.., Some(mut controller), _)) = camera.get_single_mut() {
+            controller.enabled = false;
+        }
+        state.started = Some(Instant::now());
+        state.segment_started = state.started;
+        state.frame = 0;
+        state.tween_started = None;
+        state.segment_frame_times.clear();
+        state.segment_rendered_meshes.clear();
+        state.frame_times.clear();
+        state.last_frame = None;
+        if let Some(gpu_timings) = &gpu_timings {
+            gpu_timings.reset();
+        }
+        if let Some(frames) = args.bench_fixed_frames {
+            state.count_per_step = frames;
+            commands.insert_resource(TimeUpdateStrategy::ManualDuration(FIXED_FRAME_TIME));
+        } else {
+            // Try to render for around 2s or at least 30 frames per step
+            state.count_per_step = ((2.0 / time.delta_seconds()) as u32).max(30);
+        }
+        println!(
+            "Starting Benchmark with {} frames at each of {} positions after {} warmup frames",
+            state.count_per_step,
+            camera_positions.len(),
+            args.bench_warmup
+        );
+        if !args.bench_headless
+            && matches!(
+                args.present_mode,
+                PresentMode::Fifo | PresentMode::FifoRelaxed | PresentMode::AutoVsync
+            )
+        {
+            warn!(
+                "Benchmarking with vsync ({:?}), frame times will be limited by the refresh rate",
+                args.present_mode
+            );
+        }
+    }
+    if state.started.is_none() {
+        return;
+    }
+    let now = Instant::now();
+    let frame_time = state
+        .last_frame
+        .replace(now)
+        .map(|last| (now - last).as_secs_f32());
+    let Ok((entity, mut transform, tweening, mut controller, visible)) = camera.get_single_mut()
+    else {
+        return;
+    };
+    if tweening {
+        state.tween_started.get_or_insert_with(Instant::now);
+        return;
+    }
+    if state.tween_started.take().is_some() {
+        state.segment_started = Some(Instant::now());
+    }
+    let mut move_camera = |transform: &mut Transform, target: Transform| {
+        move_camera_to(&mut commands, entity, transform, target, args.cam_smooth);
+    };
+    let count_per_step = state.count_per_step;
+    let warmup = args.bench_warmup;
+    // Each position renders the warmup frames first, which aren't counted
+    let step = warmup + count_per_step;
+    let step_frame = match state.frame % step {
+        0 if state.frame > 0 => step,
+        frame => frame,
+    };
+    if let Some(frame_time) = frame_time.filter(|_| step_frame > warmup) {
+        state.frame_times.push(frame_time);
+    }
+    if state.frame > 0 && state.frame.is_multiple_of(step) {
+        let segment_elapsed = state.segment_started.unwrap().elapsed().as_secs_f32();
+        state
+            .segment_frame_times
+            .push(segment_elapsed / count_per_step as f32);
+        // Counted once, the camera doesn't move during a segment
+        state
+            .segment_rendered_meshes
+            .push(scene.rendered_meshes(visible));
+        if args.capture {
+            // Capture right before moving on, after TAA had the whole segment to converge
+            let position = (state.frame / step - 1) as usize;
+            screenshots.send(CaptureScreenshot {
+                position: Some(position),
+            });
+        }
+    }
+    if state.frame % step == warmup {
+        state.segment_started = Some(Instant::now());
+    }
+    let position = (state.frame / step) as usize;
+    if state.frame.is_multiple_of(step) && position < camera_positions.len() {
+        move_camera(&mut transform, camera_positions[position])
+    } else if state.frame == step * camera_positions.len() as u32 {
+        // Every position counts the same number of frames
+        let avg_frame_time =
+            state.segment_frame_times.iter().sum::<f32>() / state.segment_frame_times.len() as f32;
+        let mut frame_times = std::mem::take(&mut state.frame_times);
+        frame_times.sort_by(f32::total_cmp);
+        let result = BenchmarkResult {
+            segment_frame_times: std::mem::take(&mut state.segment_frame_times),
+            segment_rendered_meshes: std::mem::take(&mut state.segment_rendered_meshes),
+            avg_frame_time,
+            p50_frame_time: percentile(&frame_times, 50.0),
+            p95_frame_time: percentile(&frame_times, 95.0),
+            p99_frame_time: percentile(&frame_times, 99.0),
+            max_frame_time: frame_times.last().copied().unwrap_or_default(),
+            gpu_pass_times: gpu_timings.and_then(|timings| timings.averages()),
+            scene_instances: counts.scene_roots.iter().count(),
+            mesh_entities: scene.mesh_entities(),
+            meshes: counts.meshes.len(),
+            materials: counts.materials.len(),
+        };
+        result.print();
+        if let Some(path) = &args.bench_csv {
+            match result.append_csv(Path::new(path)) {
+                Ok(()) => println!("Benchmark results appended to {path}"),
+                Err(e) => warn!("Failed to write benchmark csv {path}: {e}"),
+            }
+        }
+        if let Some(path) = &args.bench_json {
+            match result.write_json(Path::new(path), &args) {
+                Ok(()) => println!("Benchmark results written to {path}"),
+                Err(e) => warn!("Failed to write benchmark json {path}: {e:#}"),
+            }
+        }
+        state.started = None;
+        running.0 = false;
+        state.frame = 0;
+        let sweep_continues = sweep
+            .as_mut()
+            .is_some_and(|sweep| sweep.finish_run(avg_frame_time));
+        move_camera(&mut transform, camera_positions[0]);
+        if let Some(controller) = &mut controller {
+            // A tween back to the start keeps it disabled until it finishes
+            controller.enabled = true;
+            controller.initialized = false;
+        }
+        if args.bench_fixed_frames.is_some() {
+            commands.insert_resource(TimeUpdateStrategy::Automatic);
+        }
+        if args.bench_headless && !sweep_continues {
+            state.exit_countdown = Some(HEADLESS_EXIT_FRAMES);
+        }
+    }
+    state.frame += 1;
+}
@@ -20,11 +20,20 @@ pub struct CameraController {
     pub key_run: KeyCode,
     pub mouse_key_enable_mouse: MouseButton,
     pub keyboard_key_enable_mouse: KeyCode,
+    pub key_toggle_orbit: KeyCode,
+    pub key_roll_left: KeyCode,
+    pub key_roll_right: KeyCode,
     pub walk_speed: f32,
     pub run_speed: f32,
     pub friction: f32,
     pub pitch: f32,
     pub yaw: f32,
+    /// Rotation around the view direction in radians
+    pub roll: f32,
+    /// Radians per second
+    pub roll_speed: f32,
+    /// Roll is clamped to +-this many radians, `None` lets it go all the way around
+    pub max_roll: Option<f32>,
     pub velocity: Vec3,
     pub orbit_focus: Vec3,
     pub orbit_mode: bool,
@@ -47,6 +56,8 @@ impl CameraController {
     {:?} - Down
     {:?} - Run
     {:?}/{:?} - EnableMouse
+    {:?} - Toggle orbit
+    {:?}/{:?} - Roll
 ",
             self.key_forward,
             self.key_back,
@@ -57,6 +68,9 @@ impl CameraController {
             self.key_run,
             self.mouse_key_enable_mouse,
             self.keyboard_key_enable_mouse,
+            self.key_toggle_orbit,
+            self.key_roll_left,
+            self.key_roll_right,
         );
         self
     }
@@ -77,11 +91,17 @@ impl Default for CameraController {
             key_run: KeyCode::ShiftLeft,
             mouse_key_enable_mouse: MouseButton::Left,
             keyboard_key_enable_mouse: KeyCode::KeyM,
+            key_toggle_orbit: KeyCode::F3,
+            key_roll_left: KeyCode::ArrowLeft,
+            key_roll_right: KeyCode::ArrowRight,
             walk_speed: 5.0,
             run_speed: 15.0,
             friction: 0.5,
             pitch: 0.0,
             yaw: 0.0,
+            roll: 0.0,
+            roll_speed: 0.5,
+            max_roll: None,
             velocity: Vec3::ZERO,
             orbit_focus: Vec3::ZERO,
             orbit_mode: false,
@@ -92,27 +112,55 @@ impl Default for CameraController {
 }
 
 pub fn camera_controller(
-    time: Res<Time>,
+    time: Res<Time<Real>>,
     mut mouse_events: EventReader<MouseMotion>,
     mouse_button_input: Res<ButtonInput<MouseButton>>,
     mut scroll_evr: EventReader<MouseWheel>,
     key_input: Res<ButtonInput<KeyCode>>,
     mut move_toggled: Local<bool>,
-    mut query: Query<(&mut Transform, &mut CameraController), With<Camera>>,
+    mut query: Query<
+        (
+            &mut Transform,
+            &mut CameraController,
+            Option<&mut Projection>,
+        ),
+        With<Camera>,
+    >,
 ) {
     let dt = time.delta_seconds();
 
-    if let Ok((mut transform, mut options)) = query.get_single_mut() {
+    if let Ok((mut transform, mut options, projection)) = query.get_single_mut() {
         if !options.initialized {
-            let (_roll, yaw, pitch) = transform.rotation.to_euler(EulerRot::ZYX);
+            let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
             options.yaw = yaw;
             options.pitch = pitch;
+            options.roll = roll;
             options.initialized = true;
         }
         if !options.enabled {
             return;
         }
 
+        if key_input.just_pressed(options.key_toggle_orbit) {
+            options.orbit_mode = !options.orbit_mode;
+            options.velocity = Vec3::ZERO;
+            if options.orbit_mode {
+                // Face the pivot so orbiting starts from the current position
+                let focus = options.orbit_focus;
+                if transform.translation.distance_squared(focus) > 1e-6 {
+                    transform.look_at(focus, Vec3::Y);
+                    let (yaw, pitch, _roll) = transform.rotation.to_euler(EulerRot::YXZ);
+                    options.yaw = yaw;
+                    options.pitch = pitch;
+                    options.roll = 0.0;
+                }
+            }
+            println!(
+                "Camera mode: {}",
+                if options.orbit_mode { "orbit" } else { "free" }
+            );
+        }
+
         let mut scroll_distance = 0.0;
 
         // Handle scroll input
@@ -148,6 +196,13 @@ pub fn camera_controller(
         if key_input.just_pressed(options.keyboard_key_enable_mouse) {
             *move_toggled = !*move_toggled;
         }
+        let mut roll_input = 0.0;
+        if key_input.pressed(options.key_roll_left) {
+            roll_input += 1.0;
+        }
+        if key_input.pressed(options.key_roll_right) {
+            roll_input -= 1.0;
+        }
 
         // Apply movement update
         if axis_input != Vec3::ZERO {
@@ -171,16 +226,29 @@ pub fn camera_controller(
             + options.velocity.z * dt * *forward;
         let mut scroll_translation = Vec3::ZERO;
         if options.orbit_mode && options.scroll_wheel_speed > 0.0 {
-            scroll_translation = scroll_distance
-                * transform.translation.distance(options.orbit_focus)
-                * options.scroll_wheel_speed
-                * *forward;
+            match projection.map(|p| p.into_inner()) {
+                // Moving closer doesn't change the size of an orthographic view, zoom instead
+                Some(Projection::Orthographic(ortho)) => {
+                    ortho.scale = (ortho.scale
+                        * (1.0 - scroll_distance * options.scroll_wheel_speed))
+                        .max(0.01);
+                }
+                _ => {
+                    scroll_translation = scroll_distance
+                        * transform.translation.distance(options.orbit_focus)
+                        * options.scroll_wheel_speed
+                        * *forward;
+                }
+            }
         }
         if options.lock_y {
             translation_delta *= Vec3::new(1.0, 0.0, 1.0);
         }
         transform.translation += translation_delta + scroll_translation;
-        options.orbit_focus += translation_delta;
+        if options.orbit_mode {
+            // Pan the pivot along with the camera
+            options.orbit_focus += translation_delta;
+        }
 
         // Handle mouse input
         let mut mouse_delta = Vec2::ZERO;
@@ -192,7 +260,7 @@ pub fn camera_controller(
             mouse_events.clear();
         }
 
-        if mouse_delta != Vec2::ZERO {
+        if mouse_delta != Vec2::ZERO || roll_input != 0.0 {
             let sensitivity = if options.orbit_mode {
                 options.sensitivity * 2.0
             } else {
@@ -205,11 +273,16 @@ pub fn camera_controller(
                 ),
                 options.yaw - mouse_delta.x * sensitivity * dt,
             );
+            let mut roll = options.roll + roll_input * options.roll_speed * dt;
+            if let Some(max_roll) = options.max_roll {
+                roll = roll.clamp(-max_roll, max_roll);
+            }
 
-            // Apply look update
-            transform.rotation = Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch);
+            // Apply look update, roll turns around the view direction after yaw and pitch
+            transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
             options.pitch = pitch;
             options.yaw = yaw;
+            options.roll = roll;
 
             if options.orbit_mode {
                 let rot_matrix = Mat3::from_quat(transform.rotation);
@@ -0,0 +1,192 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::{
+    benchmark::BenchmarkRunning, camera_controller::CameraController, Args, CAM_POS_1, CAM_POS_2,
+    CAM_POS_3,
+};
+
+// How often the --cam-positions file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// File that positions recorded with `I` are written to when pressing `O`
+pub const RECORDED_CAMERA_POSITIONS_PATH: &str = "recorded_camera_positions.ron";
+
+/// Saved camera positions, the first nine are bound to the number keys.
+#[derive(Resource, Deref, DerefMut, Clone)]
+pub struct CameraPositions(pub Vec<Transform>);
+
+impl Default for CameraPositions {
+    fn default() -> Self {
+        Self(vec![CAM_POS_1, CAM_POS_2, CAM_POS_3])
+    }
+}
+
+impl CameraPositions {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read camera positions {}", path.display()))?;
+        let positions = ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse camera positions {}", path.display()))?;
+        Ok(Self(positions))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let contents = ron::ser::to_string_pretty(&self.0, PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// `transform` as a `Transform { .. }` literal in the style of [`CAM_POS_1`]
+pub fn transform_literal(transform: &Transform) -> String {
+    let Vec3 { x, y, z } = transform.translation;
+    let [qx, qy, qz, qw] = transform.rotation.to_array();
+    format!(
+        "Transform {{
+    translation: Vec3::new({x:?}, {y:?}, {z:?}),
+    rotation: Quat::from_array([{qx:?}, {qy:?}, {qz:?}, {qw:?}]),
+    scale: Vec3::ONE,
+}}"
+    )
+}
+
+/// The system clipboard, opened the first time something is copied. A non-send resource, it
+/// isn't `Send` on every platform. Kept open for the whole run, on X11 the copied text is served
+/// by the clipboard that set it and is gone once that's dropped.
+#[derive(Default)]
+pub struct Clipboard(Option<arboard::Clipboard>);
+
+impl Clipboard {
+    pub fn copy(&mut self, text: &str) -> anyhow::Result<()> {
+        if self.0.is_none() {
+            self.0 = Some(arboard::Clipboard::new()?);
+        }
+        self.0.as_mut().unwrap().set_text(text)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct WatchState {
+    last_check: Option<Instant>,
+    modified: Option<SystemTime>,
+}
+
+/// Reloads --cam-positions when the file changes, so positions can be edited while the app runs.
+/// Invalid edits are reported and the previous positions kept. Waits for a running benchmark.
+pub fn watch_camera_positions(
+    args: Res<Args>,
+    benchmark: Res<BenchmarkRunning>,
+    mut camera_positions: ResMut<CameraPositions>,
+    mut state: Local<WatchState>,
+) {
+    let Some(path) = &args.cam_positions else {
+        return;
+    };
+    if benchmark.0
+        || state
+            .last_check
+            .is_some_and(|last| last.elapsed() < WATCH_INTERVAL)
+    {
+        return;
+    }
+    state.last_check = Some(Instant::now());
+    let Ok(modified) = fs::metadata(path).and_then(|metadata| metadata.modified()) else {
+        return;
+    };
+    // The first check only records the time of the file loaded at startup
+    let changed = state.modified.is_some_and(|last| last != modified);
+    state.modified = Some(modified);
+    if !changed {
+        return;
+    }
+    match CameraPositions::load(path) {
+        Ok(positions) if positions.is_empty() => {
+            warn!("{path} has no camera positions, keeping the previous ones");
+        }
+        Ok(positions) => {
+            println!("Reloaded {} camera positions from {path}", positions.len());
+            *camera_positions = positions;
+        }
+        Err(e) => warn!("{e:#}, keeping the previous camera positions"),
+    }
+}
+
+/// Positions printed with `I` during this session
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct RecordedCameraPositions(pub Vec<Transform>);
+
+/// Animates the camera from `start` to `end` over `duration` seconds.
+/// The [`CameraController`] is disabled until the tween finishes.
+#[derive(Component)]
+pub struct CameraTween {
+    pub start: Transform,
+    pub end: Transform,
+    pub duration: f32,
+    pub elapsed: f32,
+}
+
+/// Moves the camera to `target`, either instantly or with a [`CameraTween`] lasting `smooth` seconds.
+pub fn move_camera_to(
+    commands: &mut Commands,
+    camera: Entity,
+    transform: &mut Transform,
+    target: Transform,
+    smooth: Option<f32>,
+) {
+    match smooth {
+        Some(duration) if duration > 0.0 => {
+            commands.entity(camera).insert(CameraTween {
+                start: *transform,
+                end: target,
+                duration,
+                elapsed: 0.0,
+            });
+        }
+        _ => *transform = target,
+    }
+}
+
+pub fn tween_camera(
+    mut commands: Commands,
+    time: Res<Time>,
+    benchmark: Res<BenchmarkRunning>,
+    mut cameras: Query<(
+        Entity,
+        &mut Transform,
+        &mut CameraTween,
+        Option<&mut CameraController>,
+    )>,
+) {
+    for (entity, mut transform, mut tween, controller) in &mut cameras {
+        tween.elapsed += time.delta_seconds();
+        let t = (tween.elapsed / tween.duration).min(1.0);
+        // Ease in and out
+        let t = t * t * (3.0 - 2.0 * t);
+        transform.translation = tween.start.translation.lerp(tween.end.translation, t);
+        transform.rotation = tween.start.rotation.slerp(tween.end.rotation, t);
+        let finished = tween.elapsed >= tween.duration;
+        if let Some(mut controller) = controller {
+            // The benchmark keeps the controller disabled between its moves
+            controller.enabled = finished && !benchmark.0;
+            if finished {
+                // Pick up the new yaw/pitch from the transform
+                controller.initialized = false;
+                controller.velocity = Vec3::ZERO;
+            }
+        }
+        if finished {
+            *transform = tween.end;
+            commands.entity(entity).remove::<CameraTween>();
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use bevy::{pbr::CascadeShadowConfig, prelude::*};
+
+use crate::{key_bindings::KeyBindings, minimap::MinimapCamera, SunLight};
+
+// Color of each shadow cascade, nearest first
+const CASCADE_COLORS: [Color; 4] = [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW];
+
+/// V, with --debug-cascades: draws the part of the view each sun shadow cascade covers.
+/// The cascades are frozen where the camera was when V was pressed, so they can be looked at
+/// from outside. Pressing V again hides them.
+#[allow(clippy::type_complexity)]
+pub fn debug_cascades(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    camera: Query<(&GlobalTransform, &Projection), (With<Camera>, Without<MinimapCamera>)>,
+    sun: Query<&CascadeShadowConfig, With<SunLight>>,
+    mut frozen: Local<Option<(GlobalTransform, Projection)>>,
+    mut gizmos: Gizmos,
+) {
+    if input.just_pressed(keys.freeze_cascades) && frozen.take().is_none() {
+        let Ok((transform, projection)) = camera.get_single() else {
+            return;
+        };
+        *frozen = Some((*transform, projection.clone()));
+        if let Ok(config) = sun.get_single() {
+            let bounds = config
+                .bounds
+                .iter()
+                .map(|bound| format!("{bound:.1}m"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("Shadow cascade far bounds: {bounds}");
+        }
+    }
+    let (Some((transform, projection)), Ok(config)) = (&*frozen, sun.get_single()) else {
+        return;
+    };
+    let mut near = config.minimum_distance;
+    for (i, far) in config.bounds.iter().enumerate() {
+        let color = CASCADE_COLORS[i % CASCADE_COLORS.len()];
+        let near_corners = slice_corners(projection, near).map(|c| transform.transform_point(c));
+        let far_corners = slice_corners(projection, *far).map(|c| transform.transform_point(c));
+        for corners in [near_corners, far_corners] {
+            gizmos.linestrip(corners.into_iter().chain([corners[0]]), color);
+        }
+        for (near_corner, far_corner) in near_corners.into_iter().zip(far_corners) {
+            gizmos.line(near_corner, far_corner, color);
+        }
+        // Bevy starts each cascade a bit before the previous one ends
+        near = far * (1.0 - config.overlap_proportion);
+    }
+}
+
+/// View space corners of the camera's view `depth` in front of it
+pub fn slice_corners(projection: &Projection, depth: f32) -> [Vec3; 4] {
+    let (half_width, half_height) = match projection {
+        Projection::Perspective(perspective) => {
+            let half_height = depth * (perspective.fov * 0.5).tan();
+            (half_height * perspective.aspect_ratio, half_height)
+        }
+        Projection::Orthographic(orthographic) => (
+            orthographic.area.width() * 0.5,
+            orthographic.area.height() * 0.5,
+        ),
+    };
+    [
+        Vec3::new(-half_width, -half_height, -depth),
+        Vec3::new(half_width, -half_height, -depth),
+        Vec3::new(half_width, half_height, -depth),
+        Vec3::new(-half_width, half_height, -depth),
+    ]
+}
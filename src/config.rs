@@ -0,0 +1,209 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use argh::{EarlyExit, FromArgs};
+use toml_edit::{Document, Item, Value};
+
+use crate::Args;
+
+/// Parses the command line like `argh::from_env`, adding the settings from `--config <path>`.
+/// Flags given on the command line take precedence over the file, the file over the defaults.
+pub fn args_from_env() -> Args {
+    let strings = std::env::args().collect::<Vec<_>>();
+    let cmd = Path::new(&strings[0])
+        .file_name()
+        .map_or_else(|| strings[0].clone(), |name| name.to_string_lossy().into());
+    let cli = &strings[1..];
+    let merged = match config_path(cli) {
+        Some(path) => match config_args(Path::new(path)) {
+            Ok(config) => merge_args(config, cli),
+            Err(e) => {
+                eprintln!("Failed to load config: {e:#}");
+                std::process::exit(1);
+            }
+        },
+        None => cli.to_vec(),
+    };
+    let merged = merged.iter().map(String::as_str).collect::<Vec<_>>();
+    match Args::from_args(&[&cmd], &merged) {
+        Ok(args) => args,
+        Err(EarlyExit { output, status }) => match status {
+            Ok(()) => {
+                println!("{output}");
+                std::process::exit(0);
+            }
+            Err(()) => {
+                eprintln!("{output}\nRun {cmd} --help for more information.");
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+fn config_path(cli: &[String]) -> Option<&str> {
+    cli.iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| cli.get(i + 1))
+        .map(String::as_str)
+}
+
+/// The file's settings as command line arguments. Keys are the flag names with or without
+/// dashes, e.g. `cam_speed = 2.0`, switches are set with `true`, vectors are arrays.
+fn config_args(path: &Path) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_config(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn parse_config(contents: &str) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    let document = contents.parse::<Document>()?;
+    let mut args = Vec::new();
+    for (key, item) in document.iter() {
+        let flag = format!("--{}", key.replace('_', "-"));
+        if flag == "--config" {
+            bail!("config files can't include other configs");
+        }
+        let Item::Value(value) = item else {
+            bail!("{key} must be a value, not a table");
+        };
+        match value {
+            // Switches can only be turned on, a switch in the file can't be turned off from the
+            // command line either
+            Value::Boolean(enabled) => {
+                if !*enabled.value() {
+                    bail!("{key} can only be set to true, remove it to leave the switch off");
+                }
+                args.push((flag, None));
+            }
+            Value::Array(array) => {
+                let values = array.iter().map(scalar).collect::<anyhow::Result<Vec<_>>>();
+                let values = values.with_context(|| format!("invalid {key}"))?;
+                args.push((flag, Some(values.join(","))));
+            }
+            value => {
+                let value = scalar(value).with_context(|| format!("invalid {key}"))?;
+                args.push((flag, Some(value)));
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn scalar(value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::String(s) => Ok(s.value().clone()),
+        Value::Integer(i) => Ok(i.value().to_string()),
+        Value::Float(f) => Ok(f.value().to_string()),
+        _ => bail!("expected a string or number, got {}", value.type_name()),
+    }
+}
+
+/// The config flags that aren't on the command line, followed by the command line. Flags are
+/// matched by their exact string, argh only accepts `--flag value`, not `--flag=value`.
+fn merge_args(config: Vec<(String, Option<String>)>, cli: &[String]) -> Vec<String> {
+    let mut merged = Vec::new();
+    for (flag, value) in config {
+        if cli.contains(&flag) {
+            continue;
+        }
+        merged.push(flag);
+        merged.extend(value);
+    }
+    merged.extend_from_slice(cli);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn merge(config: &str, cli: &[&str]) -> Vec<String> {
+        merge_args(parse_config(config).unwrap(), &strings(cli))
+    }
+
+    fn parse(config: &str, cli: &[&str]) -> Args {
+        let merged = merge(config, cli);
+        let merged = merged.iter().map(String::as_str).collect::<Vec<_>>();
+        match Args::from_args(&["san_miguel"], &merged) {
+            Ok(args) => args,
+            Err(EarlyExit { output, .. }) => panic!("{merged:?}: {output}"),
+        }
+    }
+
+    #[test]
+    fn cli_beats_file_beats_default() {
+        let args = parse("fov = 70.0\ncam_speed = 2.0", &["--cam-speed", "5"]);
+        assert_eq!(args.fov, 70.0);
+        assert_eq!(args.cam_speed, Some(5.0));
+        assert_eq!(args.shadow_distance, 30.0);
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        assert_eq!(
+            merge("cam_speed = 2.0", &["--cam-speed", "5"]),
+            strings(&["--cam-speed", "5"])
+        );
+    }
+
+    #[test]
+    fn file_fills_in_missing_flags() {
+        assert_eq!(
+            merge(
+                "cam-speed = 2\nminimal = true\ncam_positions = \"positions.ron\"",
+                &["--fov", "60"]
+            ),
+            strings(&[
+                "--cam-speed",
+                "2",
+                "--minimal",
+                "--cam-positions",
+                "positions.ron",
+                "--fov",
+                "60"
+            ])
+        );
+        let args = parse("minimal = true\ncam_positions = \"positions.ron\"", &[]);
+        assert!(args.minimal);
+        assert_eq!(args.cam_positions.as_deref(), Some("positions.ron"));
+    }
+
+    #[test]
+    fn arrays_become_one_list_flag() {
+        assert_eq!(
+            merge("orbit_pivot = [1, 2.5, -3]", &[]),
+            strings(&["--orbit-pivot", "1,2.5,-3"])
+        );
+        assert_eq!(
+            parse("orbit_pivot = [1, 2.5, -3]", &[]).orbit_pivot,
+            Vec3::new(1.0, 2.5, -3.0)
+        );
+        assert_eq!(
+            parse("orbit_pivot = [1, 2.5, -3]", &["--orbit-pivot", "0,1,0"]).orbit_pivot,
+            Vec3::Y
+        );
+    }
+
+    #[test]
+    fn repeated_positionals_follow_the_file() {
+        let args = parse("fov = 70.0", &["base.json", "new.json"]);
+        assert_eq!(args.bench_files, ["base.json", "new.json"]);
+        assert_eq!(args.fov, 70.0);
+    }
+
+    #[test]
+    fn false_switches_are_rejected() {
+        assert!(parse_config("minimal = false").is_err());
+    }
+
+    #[test]
+    fn configs_cant_include_configs() {
+        assert!(parse_config("config = \"other.toml\"").is_err());
+    }
+}
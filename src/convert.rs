@@ -1,61 +1,443 @@
+use anyhow::anyhow;
 use threadpool::ThreadPool;
 
-use std::{fs, io::Write, process::Command, thread::available_parallelism};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::available_parallelism,
+};
 
-pub fn change_gltf_to_use_ktx2() {
+pub const DEFAULT_CONVERT_DIR: &str = "./assets/san-miguel/";
+
+/// Block compression format used for the converted color textures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    Bc7,
+    Bc5,
+    Bc3,
+    Bc1,
+    Astc,
+}
+
+impl CompressionFormat {
+    /// Format name as understood by kram
+    fn kram_name(self) -> &'static str {
+        match self {
+            CompressionFormat::Bc7 => "bc7",
+            CompressionFormat::Bc5 => "bc5",
+            CompressionFormat::Bc3 => "bc3",
+            CompressionFormat::Bc1 => "bc1",
+            CompressionFormat::Astc => "astc4x4",
+        }
+    }
+
+    fn supports_alpha(self) -> bool {
+        !matches!(self, CompressionFormat::Bc5 | CompressionFormat::Bc1)
+    }
+
+    /// Normal maps need two full precision channels
+    fn for_normal_map(self) -> Self {
+        match self {
+            CompressionFormat::Astc => CompressionFormat::Astc,
+            _ => CompressionFormat::Bc5,
+        }
+    }
+}
+
+impl FromStr for CompressionFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bc7" => Ok(CompressionFormat::Bc7),
+            "bc5" => Ok(CompressionFormat::Bc5),
+            "bc3" => Ok(CompressionFormat::Bc3),
+            "bc1" => Ok(CompressionFormat::Bc1),
+            "astc" => Ok(CompressionFormat::Astc),
+            _ => Err(format!(
+                "unknown compression format {s}, expected one of bc7, bc5, bc3, bc1, astc"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CompressionFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kram_name())
+    }
+}
+
+/// How much time kram spends searching for better block encodings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionSpeed {
+    UltraFast,
+    Fast,
+    Normal,
+    Slow,
+}
+
+impl CompressionSpeed {
+    /// kram's -quality, 0 to 100. Normal is kram's default of 49
+    fn kram_quality(self) -> u32 {
+        match self {
+            CompressionSpeed::UltraFast => 0,
+            CompressionSpeed::Fast => 25,
+            CompressionSpeed::Normal => 49,
+            CompressionSpeed::Slow => 100,
+        }
+    }
+}
+
+impl FromStr for CompressionSpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ultrafast" => Ok(CompressionSpeed::UltraFast),
+            "fast" => Ok(CompressionSpeed::Fast),
+            "normal" => Ok(CompressionSpeed::Normal),
+            "slow" => Ok(CompressionSpeed::Slow),
+            _ => Err(format!(
+                "unknown compression speed {s}, expected one of ultrafast, fast, normal, slow"
+            )),
+        }
+    }
+}
+
+/// Returns the gltf files in `dir`, errors if there are none or only binary glb files.
+pub fn find_gltf_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut gltf_files = Vec::new();
+    let mut glb_files = Vec::new();
+    for entry in fs::read_dir(dir)
+        .map_err(|e| anyhow!("Failed to read convert dir {}: {e}", dir.display()))?
     {
-        let path = "./assets/san-miguel/san-miguel.gltf";
-        let contents = fs::read_to_string(path).unwrap();
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gltf") => gltf_files.push(path),
+            Some("glb") => glb_files.push(path),
+            _ => (),
+        }
+    }
+    // The texture uris are rewritten in the json, a binary glb would be corrupted.
+    // Next to .gltf files they are left alone.
+    if gltf_files.is_empty() && !glb_files.is_empty() {
+        return Err(anyhow!(
+            "{} is a .glb, convert requires text glTF with external textures",
+            glb_files[0].display()
+        ));
+    }
+    if gltf_files.is_empty() {
+        return Err(anyhow!("No .gltf file found in {}", dir.display()));
+    }
+    Ok(gltf_files)
+}
+
+/// Rewrites the gltf files in `src` to use ktx2 textures and writes them to `dst`.
+/// If `dst` is a different directory the `.bin` buffers are copied along so the result loads.
+/// With `dry_run` the files and texture uris that would change are only printed.
+pub fn change_gltf_to_use_ktx2(src: &Path, dst: &Path, dry_run: bool) -> anyhow::Result<()> {
+    if !dry_run {
+        fs::create_dir_all(dst)?;
+    }
+    let mut gltf_count = 0;
+    let mut uri_count = 0;
+    for path in find_gltf_files(src)? {
+        let contents = fs::read_to_string(&path)?;
+        let new_path = dst.join(path.file_name().unwrap());
+        if dry_run {
+            println!("would rewrite {}", new_path.display());
+            for uri in png_image_uris(&contents)? {
+                println!("    {uri} -> {}", uri.replace(".png", ".ktx2"));
+                uri_count += 1;
+            }
+            gltf_count += 1;
+            continue;
+        }
         let new = contents
             .replace("\"mimeType\":\"image/png\",", "")
             .replace(".png", ".ktx2");
         let mut file = fs::OpenOptions::new()
+            .create(true)
             .write(true)
             .truncate(true)
-            .open(path)
-            .unwrap();
-        let _ = file.write(new.as_bytes()).unwrap();
-    }
-}
-
-pub fn convert_images_to_ktx2() {
-    for path in ["./assets/san-miguel/"] {
-        let pool = ThreadPool::new(available_parallelism().unwrap().get());
-        for path in fs::read_dir(path).unwrap() {
-            pool.execute(move || {
-                if let Ok(path) = path {
-                    let path = path.path();
-                    if path.is_file() && path.extension().unwrap() == "png" {
-                        let path_string = path.to_string_lossy().to_string();
-                        let new_path_string =
-                            path.with_extension("ktx2").to_string_lossy().to_string();
-                        let name = path.file_stem().unwrap().to_string_lossy().to_lowercase();
-                        let nor = name.contains("Normal");
-
-                        let mut cmd = Command::new("kram");
-                        cmd.arg("encode").arg("-f");
-                        // should be able to use bc5 for nor and rough+metal, but they looked bad
-                        cmd.arg("bc7");
-                        if nor {
-                            cmd.arg("-normal");
-                        }
-                        cmd.arg("-type")
-                            .arg("2d")
-                            .arg("-mipmin")
-                            .arg("1")
-                            .arg("-srgb")
-                            .arg("-zstd")
-                            .arg("0")
-                            .arg("-i")
-                            .arg(path_string)
-                            .arg("-o")
-                            .arg(new_path_string);
-                        dbg!(&cmd);
-                        cmd.output().expect("ls command failed to start");
-                    }
+            .open(new_path)?;
+        file.write_all(new.as_bytes())?;
+    }
+    let mut bin_count = 0;
+    if !same_dir(src, dst) {
+        for entry in fs::read_dir(src)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "bin") {
+                let new_path = dst.join(path.file_name().unwrap());
+                if dry_run {
+                    println!("would write {}", new_path.display());
+                    bin_count += 1;
+                } else {
+                    fs::copy(&path, new_path)?;
                 }
-            });
+            }
         }
-        pool.join();
     }
+    if dry_run {
+        println!(
+            "Dry run: {gltf_count} gltf files would be rewritten, {uri_count} texture uris changed, {bin_count} buffers copied"
+        );
+    }
+    Ok(())
+}
+
+/// Image uris in the gltf json that the ktx2 rewrite changes
+fn png_image_uris(gltf: &str) -> anyhow::Result<Vec<String>> {
+    let json: serde_json::Value = serde_json::from_str(gltf)?;
+    Ok(json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["uri"].as_str())
+        .filter(|uri| uri.contains(".png"))
+        .map(String::from)
+        .collect())
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Returns the png textures in `dir`.
+pub fn find_source_images(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "png") {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Converts the png textures in `src` to ktx2 files in `dst`.
+/// Each image is converted independently on up to `threads` threads (default: all cores),
+/// lower this if decoding many large textures at once runs out of memory.
+/// `format` overrides the format of color textures, normal maps always use a two channel format.
+/// `speed` trades encoding time for quality.
+/// With `zstd_level` the output is supercompressed with zstd (0 is kram's default level),
+/// `None` writes plain ktx2.
+/// With `dry_run` the files that would be written are only printed.
+pub fn convert_images_to_ktx2(
+    src: &Path,
+    dst: &Path,
+    threads: Option<usize>,
+    format: Option<CompressionFormat>,
+    speed: CompressionSpeed,
+    zstd_level: Option<i32>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let images = find_source_images(src)?;
+    let total = images.len();
+    let normal_maps = Arc::new(normal_map_stems(src)?);
+    if dry_run {
+        for path in &images {
+            let normal_map = if is_normal_map(path, &normal_maps) {
+                " (normal map)"
+            } else {
+                ""
+            };
+            println!("would write {}{normal_map}", ktx2_path(path, dst).display());
+        }
+        println!("Dry run: {total} images would be converted");
+        return Ok(());
+    }
+    fs::create_dir_all(dst)?;
+    let threads = threads.unwrap_or_else(|| available_parallelism().unwrap().get());
+    println!("Converting {total} images using {threads} threads at {speed:?} speed");
+    let pool = ThreadPool::new(threads);
+    let done = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicUsize::new(0));
+    for path in &images {
+        let path = path.clone();
+        let dst = dst.to_path_buf();
+        let done = done.clone();
+        let failed = failed.clone();
+        let nor = is_normal_map(&path, &normal_maps);
+        pool.execute(move || {
+            let output = ktx2_command(&path, &dst, format, speed, zstd_level, nor).output();
+            let ok = match output {
+                Ok(output) if output.status.success() => true,
+                Ok(output) => {
+                    eprintln!(
+                        "kram failed for {}: {}",
+                        path.display(),
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                    false
+                }
+                Err(e) => {
+                    eprintln!("Failed to start kram for {}: {e}", path.display());
+                    false
+                }
+            };
+            if !ok {
+                failed.fetch_add(1, Ordering::Relaxed);
+            }
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            println!("converted {done}/{total}: {}", path.display());
+        });
+    }
+    pool.join();
+    let failed = failed.load(Ordering::Relaxed);
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {total} images failed to convert"));
+    }
+    let (mut compressed, mut uncompressed) = (0, 0);
+    for path in &images {
+        let (file_size, uncompressed_size) = ktx2_sizes(&ktx2_path(path, dst))?;
+        compressed += file_size;
+        uncompressed += uncompressed_size;
+    }
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "zstd saved {:.1}MiB ({:.1}MiB -> {:.1}MiB)",
+        mib(uncompressed.saturating_sub(compressed)),
+        mib(uncompressed),
+        mib(compressed)
+    );
+    Ok(())
+}
+
+// Size of the fixed ktx2 header and of each entry of the level index that follows it
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_INDEX_SIZE: usize = 24;
+
+/// Size of the ktx2 file at `path` and the size it would be without supercompression,
+/// from the compressed and uncompressed byte lengths in its level index.
+fn ktx2_sizes(path: &Path) -> anyhow::Result<(u64, u64)> {
+    let data = fs::read(path)?;
+    let invalid = || anyhow!("{} is not a valid ktx2 file", path.display());
+    let u32_at = |offset: usize| -> anyhow::Result<u32> {
+        let bytes = data.get(offset..offset + 4).ok_or_else(invalid)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let u64_at = |offset: usize| -> anyhow::Result<u64> {
+        let bytes = data.get(offset..offset + 8).ok_or_else(invalid)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    // levelCount, 0 means a single level whose mips are generated at load
+    let levels = u32_at(40)?.max(1) as usize;
+    let mut uncompressed = data.len() as u64;
+    for level in 0..levels {
+        let index = KTX2_HEADER_SIZE + level * KTX2_LEVEL_INDEX_SIZE;
+        let length = u64_at(index + 8)?;
+        let uncompressed_length = u64_at(index + 16)?;
+        uncompressed = uncompressed - length + uncompressed_length;
+    }
+    Ok((data.len() as u64, uncompressed))
+}
+
+pub fn ktx2_path(path: &Path, dst: &Path) -> PathBuf {
+    dst.join(path.file_name().unwrap()).with_extension("ktx2")
+}
+
+/// Whether the image at `path` has any pixel that isn't fully opaque
+fn uses_alpha(path: &Path) -> bool {
+    match image::open(path) {
+        Ok(image) => image.color().has_alpha() && image.to_rgba8().pixels().any(|p| p.0[3] < 255),
+        Err(_) => false,
+    }
+}
+
+/// File stems of the images any material of the gltf files in `dir` uses as its normal map
+pub fn normal_map_stems(dir: &Path) -> anyhow::Result<HashSet<String>> {
+    let mut stems = HashSet::new();
+    for path in find_gltf_files(dir)? {
+        for uri in normal_map_uris(&fs::read_to_string(&path)?)? {
+            if let Some(stem) = Path::new(&uri).file_stem() {
+                stems.insert(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(stems)
+}
+
+/// Uris of the images referenced by a material's `normalTexture`
+fn normal_map_uris(gltf: &str) -> anyhow::Result<Vec<String>> {
+    let json: serde_json::Value = serde_json::from_str(gltf)?;
+    let image_uri = |texture: &serde_json::Value| {
+        let texture = &json["textures"][texture["index"].as_u64()? as usize];
+        let image = &json["images"][texture["source"].as_u64()? as usize];
+        image["uri"].as_str().map(String::from)
+    };
+    Ok(json["materials"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|material| image_uri(&material["normalTexture"]))
+        .collect())
+}
+
+/// Normal maps are the images the gltf uses as `normalTexture`, or have "normal" in their name
+pub fn is_normal_map(path: &Path, normal_map_stems: &HashSet<String>) -> bool {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    normal_map_stems.contains(stem.as_ref()) || stem.to_lowercase().contains("normal")
+}
+
+fn ktx2_command(
+    path: &Path,
+    dst: &Path,
+    format: Option<CompressionFormat>,
+    speed: CompressionSpeed,
+    zstd_level: Option<i32>,
+    nor: bool,
+) -> Command {
+    let path_string = path.to_string_lossy().to_string();
+    let new_path_string = ktx2_path(path, dst).to_string_lossy().to_string();
+
+    let format = match format {
+        // Normal maps only need x and y, which bc5 stores at full precision
+        _ if nor => format.map_or(CompressionFormat::Bc5, CompressionFormat::for_normal_map),
+        Some(format) if !format.supports_alpha() && uses_alpha(path) => {
+            println!(
+                "{} uses alpha which {format} can't store, using bc3 instead",
+                path.display()
+            );
+            CompressionFormat::Bc3
+        }
+        Some(format) => format,
+        None => CompressionFormat::Bc7,
+    };
+
+    let mut cmd = Command::new("kram");
+    cmd.arg("encode").arg("-f");
+    cmd.arg(format.kram_name());
+    if nor {
+        cmd.arg("-normal");
+    }
+    cmd.arg("-type").arg("2d").arg("-mipmin").arg("1");
+    cmd.arg("-quality").arg(speed.kram_quality().to_string());
+    // Normal maps are linear data, encoding them as srgb skews the vectors
+    if !nor {
+        cmd.arg("-srgb");
+    }
+    if let Some(level) = zstd_level {
+        cmd.arg("-zstd").arg(level.to_string());
+    }
+    cmd.arg("-i")
+        .arg(path_string)
+        .arg("-o")
+        .arg(new_path_string);
+    cmd
 }
@@ -0,0 +1,95 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{export_gltf::material_index, key_bindings::KeyBindings};
+
+/// With --debug-material-colors, each material gets a flat color of its own so where one material
+/// ends and the next begins is easy to see. [`crate::proc_scene`] recolors the scene's materials
+/// as it processes them, slash switches back to the original materials and on again.
+#[derive(Resource)]
+pub struct DebugMaterialColors {
+    pub enabled: bool,
+    /// The recolored materials as they were before
+    originals: HashMap<AssetId<StandardMaterial>, StandardMaterial>,
+}
+
+impl Default for DebugMaterialColors {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            originals: HashMap::new(),
+        }
+    }
+}
+
+impl DebugMaterialColors {
+    /// Replaces the material's color and textures with a color picked from its gltf material
+    /// index, if it wasn't already
+    pub fn recolor(&mut self, handle: &Handle<StandardMaterial>, material: &mut StandardMaterial) {
+        let id = handle.id();
+        if !self.enabled || self.originals.contains_key(&id) {
+            return;
+        }
+        self.originals.insert(id, material.clone());
+        // The same gltf material gets the same color every run and after reloads, materials
+        // that didn't come from the gltf fall back to their id
+        let mut hasher = DefaultHasher::new();
+        match material_index(handle) {
+            Some(index) => index.hash(&mut hasher),
+            None => id.hash(&mut hasher),
+        }
+        let hue = (hasher.finish() % 360) as f32;
+        #[cfg(not(feature = "bevy_main"))]
+        let alpha = material.base_color.a();
+        #[cfg(feature = "bevy_main")]
+        let alpha = material.base_color.alpha();
+        material.base_color = Color::hsla(hue, 0.7, 0.5, alpha);
+        // Masked and blended materials get their shape from the texture's alpha, e.g. the
+        // foliage cutouts, so it stays and tints them instead
+        if material.alpha_mode == AlphaMode::Opaque {
+            material.base_color_texture = None;
+        }
+        material.emissive_texture = None;
+        material.metallic_roughness_texture = None;
+        material.occlusion_texture = None;
+    }
+}
+
+/// Slash: switch between the debug colors and the original materials
+pub fn toggle_debug_material_colors(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut debug_colors: ResMut<DebugMaterialColors>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Don't keep the textures of a reloaded scene's old materials alive
+    debug_colors
+        .originals
+        .retain(|id, _| materials.contains(*id));
+    if !input.just_pressed(keys.toggle_debug_colors) {
+        return;
+    }
+    debug_colors.enabled = !debug_colors.enabled;
+    if debug_colors.enabled {
+        for mat_h in &has_std_mat {
+            if let Some(material) = materials.get_mut(mat_h) {
+                debug_colors.recolor(mat_h, material);
+            }
+        }
+    } else {
+        for (id, original) in debug_colors.originals.drain() {
+            if let Some(material) = materials.get_mut(id) {
+                *material = original;
+            }
+        }
+    }
+    println!(
+        "Debug material colors: {}",
+        if debug_colors.enabled { "on" } else { "off" }
+    );
+}
@@ -0,0 +1,66 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    prelude::*,
+};
+
+fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// The --diag-file the frame time diagnostics are appended to, one
+/// `<unix time>,<fps>,<frame time ms>` line per [`LogDiagnosticsPlugin`] interval
+#[derive(Resource)]
+pub struct DiagFile {
+    file: File,
+    timer: Timer,
+}
+
+impl DiagFile {
+    /// Opens `path` for appending and marks the start of this run in it
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "# run started at {:.3}", unix_time())?;
+        Ok(Self {
+            file,
+            timer: Timer::new(
+                LogDiagnosticsPlugin::default().wait_duration,
+                TimerMode::Repeating,
+            ),
+        })
+    }
+}
+
+pub fn write_diag_file(
+    mut diag_file: ResMut<DiagFile>,
+    time: Res<Time<Real>>,
+    diagnostics: Res<DiagnosticsStore>,
+) {
+    if !diag_file.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let smoothed = |path| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or_default()
+    };
+    let line = format!(
+        "{:.3},{:.1},{:.3}",
+        unix_time(),
+        smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+        smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+    );
+    if let Err(e) = writeln!(diag_file.file, "{line}") {
+        warn!("Failed to write the diagnostics file: {e}");
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use bevy::{app::AppExit, prelude::*, utils::HashMap};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    material_overrides::{MaterialOverride, MaterialOverrides},
+    scene_file, Args, PostProcScene,
+};
+
+const DIFFUSE_TRANSMISSION_EXTENSION: &str = "KHR_materials_diffuse_transmission";
+const EMISSIVE_STRENGTH_EXTENSION: &str = "KHR_materials_emissive_strength";
+const TRANSMISSION_EXTENSION: &str = "KHR_materials_transmission";
+const VOLUME_EXTENSION: &str = "KHR_materials_volume";
+
+/// Writes a copy of the scene's gltf to --export-gltf once [`crate::proc_scene`] has processed
+/// its materials, with the alpha mode, double sidedness, transmission and thickness it set baked
+/// into the material definitions, then exits. The fields set by --material-overrides are baked
+/// too. Meshes and textures are referenced, not copied.
+pub fn export_gltf(
+    args: Res<Args>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    materials: Res<Assets<StandardMaterial>>,
+    overrides: Option<Res<MaterialOverrides>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut done: Local<bool>,
+) {
+    let Some(path) = &args.export_gltf else {
+        return;
+    };
+    if *done || !loading_scenes.is_empty() || has_std_mat.is_empty() {
+        return;
+    }
+    *done = true;
+
+    // The gltf loader labels materials by their index, the same one the json uses
+    let mut processed = HashMap::new();
+    for mat_h in &has_std_mat {
+        let (Some(index), Some(material)) = (material_index(mat_h), materials.get(mat_h)) else {
+            continue;
+        };
+        processed.entry(index).or_insert(material);
+    }
+    let src = Path::new("assets").join(scene_file(&args.scene));
+    match write_gltf(&src, Path::new(path), &processed, overrides.as_deref()) {
+        Ok(()) => println!("Wrote {} processed materials to {path}", processed.len()),
+        Err(e) => {
+            eprintln!("Failed to export the gltf: {e:#}");
+            std::process::exit(1);
+        }
+    }
+    app_exit.send(AppExit);
+}
+
+/// Index in the gltf of the material loaded from `Material{index}` or `Material{index} (inverted)`
+pub fn material_index(handle: &Handle<StandardMaterial>) -> Option<usize> {
+    let label = handle.path()?.label()?;
+    let index = label.strip_prefix("Material")?;
+    let index = index.strip_suffix(" (inverted)").unwrap_or(index);
+    index.parse().ok()
+}
+
+fn write_gltf(
+    src: &Path,
+    dst: &Path,
+    materials: &HashMap<usize, &StandardMaterial>,
+    overrides: Option<&MaterialOverrides>,
+) -> anyhow::Result<()> {
+    let contents =
+        fs::read_to_string(src).with_context(|| format!("Failed to read {}", src.display()))?;
+    let mut gltf: Value = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", src.display()))?;
+
+    let mut used_extensions = Vec::new();
+    if let Some(definitions) = gltf.get_mut("materials").and_then(Value::as_array_mut) {
+        for (index, definition) in definitions.iter_mut().enumerate() {
+            if let Some(material) = materials.get(&index) {
+                bake_material(definition, material, &mut used_extensions);
+                if let Some(material_override) = overrides.and_then(|o| o.by_index(index)) {
+                    bake_override(
+                        definition,
+                        material,
+                        material_override,
+                        &mut used_extensions,
+                    );
+                }
+            }
+        }
+    }
+    add_used_extensions(&mut gltf, &used_extensions);
+    rebase_uris(&mut gltf, src.parent().unwrap(), dst.parent().unwrap())?;
+
+    let json = serde_json::to_string_pretty(&gltf)?;
+    fs::write(dst, json).with_context(|| format!("Failed to write {}", dst.display()))
+}
+
+/// Overwrites the properties of the gltf material `definition` that proc_scene changes
+fn bake_material(
+    definition: &mut Value,
+    material: &StandardMaterial,
+    used_extensions: &mut Vec<&'static str>,
+) {
+    let Some(definition) = definition.as_object_mut() else {
+        return;
+    };
+    definition.remove("alphaCutoff");
+    let alpha_mode = match material.alpha_mode {
+        AlphaMode::Opaque => "OPAQUE",
+        AlphaMode::Mask(cutoff) => {
+            definition.insert("alphaCutoff".into(), json!(cutoff));
+            "MASK"
+        }
+        _ => "BLEND",
+    };
+    definition.insert("alphaMode".into(), json!(alpha_mode));
+    definition.insert("doubleSided".into(), json!(material.double_sided));
+
+    let extensions = definition
+        .entry("extensions")
+        .or_insert_with(|| Value::Object(Map::new()));
+    let Some(extensions) = extensions.as_object_mut() else {
+        return;
+    };
+    let mut set_factor = |extension: &'static str, factor: &str, value: f32| {
+        if value > 0.0 {
+            let properties = extensions
+                .entry(extension)
+                .or_insert_with(|| Value::Object(Map::new()));
+            properties[factor] = json!(value);
+            if !used_extensions.contains(&extension) {
+                used_extensions.push(extension);
+            }
+        } else if let Some(properties) = extensions.get_mut(extension) {
+            properties[factor] = json!(0.0);
+        }
+    };
+    set_factor(
+        DIFFUSE_TRANSMISSION_EXTENSION,
+        "diffuseTransmissionFactor",
+        material.diffuse_transmission,
+    );
+    set_factor(
+        TRANSMISSION_EXTENSION,
+        "transmissionFactor",
+        material.specular_transmission,
+    );
+    set_factor(VOLUME_EXTENSION, "thicknessFactor", material.thickness);
+    if extensions.is_empty() {
+        definition.remove("extensions");
+    }
+}
+
+/// Overwrites the properties of the gltf material `definition` that its --material-overrides
+/// entry sets, with the values the override gave `material`
+fn bake_override(
+    definition: &mut Value,
+    material: &StandardMaterial,
+    material_override: &MaterialOverride,
+    used_extensions: &mut Vec<&'static str>,
+) {
+    let Some(definition) = definition.as_object_mut() else {
+        return;
+    };
+    let pbr = definition
+        .entry("pbrMetallicRoughness")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if material_override.base_color.is_some() {
+        pbr["baseColorFactor"] = json!(material.base_color.as_linear_rgba_f32());
+    }
+    if material_override.metallic.is_some() {
+        pbr["metallicFactor"] = json!(material.metallic);
+    }
+    if material_override.roughness.is_some() {
+        pbr["roughnessFactor"] = json!(material.perceptual_roughness);
+    }
+    if material_override.emissive.is_some() {
+        let [r, g, b, _] = material.emissive.as_linear_rgba_f32();
+        // Factors are limited to 1.0, the rest goes in the strength
+        let strength = r.max(g).max(b);
+        let extensions = definition
+            .entry("extensions")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if strength > 1.0 {
+            extensions[EMISSIVE_STRENGTH_EXTENSION] = json!({ "emissiveStrength": strength });
+            if !used_extensions.contains(&EMISSIVE_STRENGTH_EXTENSION) {
+                used_extensions.push(EMISSIVE_STRENGTH_EXTENSION);
+            }
+            definition.insert(
+                "emissiveFactor".into(),
+                json!([r / strength, g / strength, b / strength]),
+            );
+        } else {
+            if let Some(extensions) = extensions.as_object_mut() {
+                extensions.remove(EMISSIVE_STRENGTH_EXTENSION);
+                if extensions.is_empty() {
+                    definition.remove("extensions");
+                }
+            }
+            definition.insert("emissiveFactor".into(), json!([r, g, b]));
+        }
+    }
+}
+
+fn add_used_extensions(gltf: &mut Value, extensions: &[&str]) {
+    if extensions.is_empty() {
+        return;
+    }
+    let used = gltf
+        .as_object_mut()
+        .unwrap()
+        .entry("extensionsUsed")
+        .or_insert_with(|| json!([]));
+    let Some(used) = used.as_array_mut() else {
+        return;
+    };
+    for extension in extensions {
+        if !used.iter().any(|used| used == extension) {
+            used.push(json!(extension));
+        }
+    }
+}
+
+/// Makes the relative buffer and image uris point at the source files from the new location
+fn rebase_uris(gltf: &mut Value, src_dir: &Path, dst_dir: &Path) -> anyhow::Result<()> {
+    let src_dir = src_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to find {}", src_dir.display()))?;
+    let dst_dir = if dst_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dst_dir
+    };
+    let dst_dir = dst_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to find {}", dst_dir.display()))?;
+    if src_dir == dst_dir {
+        return Ok(());
+    }
+    let prefix = relative_path(&dst_dir, &src_dir)?;
+    for list in ["buffers", "images"] {
+        let items = gltf.get_mut(list).and_then(Value::as_array_mut);
+        for item in items.into_iter().flatten() {
+            let Some(uri) = item["uri"].as_str() else {
+                continue;
+            };
+            if uri.starts_with("data:") || uri.contains("://") {
+                continue;
+            }
+            let rebased = prefix.join(uri).to_string_lossy().replace('\\', "/");
+            item["uri"] = json!(rebased);
+        }
+    }
+    Ok(())
+}
+
+/// Path from the directory `from` to `to`, both absolute
+fn relative_path(from: &Path, to: &Path) -> anyhow::Result<PathBuf> {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        bail!("the export has to be on the same drive as the scene");
+    }
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    path.extend(&to[common..]);
+    Ok(path)
+}
@@ -0,0 +1,157 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    benchmark::{SceneLoadState, SCENE_SETTLE_FRAMES},
+    camera_controller::CameraController,
+    camera_positions::CameraPositions,
+    minimap::MinimapCamera,
+    screenshot::CaptureScreenshot,
+    Args,
+};
+
+// Samples per spline segment used to look up positions by distance
+const ARC_LENGTH_SAMPLES: usize = 64;
+// With --capture every frame advances the flythrough by the same time, for a steady video
+const CAPTURE_FPS: f32 = 60.0;
+// Frames to keep running at the end so the last screenshot gets saved
+const EXIT_FRAMES: u32 = 10;
+
+/// Closed Catmull-Rom spline through camera transforms that can be sampled by distance,
+/// so the camera moves at a constant speed no matter how far apart the points are.
+pub struct CameraSpline {
+    transforms: Vec<Transform>,
+    /// Distance along the spline at each of the [`ARC_LENGTH_SAMPLES`] per segment
+    distances: Vec<f32>,
+}
+
+impl CameraSpline {
+    pub fn closed(transforms: &[Transform]) -> Self {
+        let mut spline = Self {
+            transforms: transforms.to_vec(),
+            distances: vec![0.0],
+        };
+        let mut last = spline.position(0.0);
+        let mut distance = 0.0;
+        for i in 1..=transforms.len() * ARC_LENGTH_SAMPLES {
+            let position = spline.position(i as f32 / ARC_LENGTH_SAMPLES as f32);
+            distance += position.distance(last);
+            spline.distances.push(distance);
+            last = position;
+        }
+        spline
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    /// Position at `u`, where each whole number is one of the transforms
+    fn position(&self, u: f32) -> Vec3 {
+        let n = self.transforms.len();
+        let segment = (u.floor() as usize) % n;
+        let t = u - u.floor();
+        let point = |offset: usize| self.transforms[(segment + offset + n - 1) % n].translation;
+        let (p0, p1, p2, p3) = (point(0), point(1), point(2), point(3));
+        0.5 * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t)
+    }
+
+    /// Transform `distance` along the spline, wrapping around at the end
+    pub fn sample(&self, distance: f32) -> Transform {
+        let distance = distance.rem_euclid(self.length().max(f32::EPSILON));
+        let i = self
+            .distances
+            .partition_point(|d| *d <= distance)
+            .clamp(1, self.distances.len() - 1);
+        let (start, end) = (self.distances[i - 1], self.distances[i]);
+        let fraction = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+        let u = (i - 1) as f32 / ARC_LENGTH_SAMPLES as f32 + fraction / ARC_LENGTH_SAMPLES as f32;
+
+        let n = self.transforms.len();
+        let segment = (u.floor() as usize) % n;
+        let rotation = self.transforms[segment]
+            .rotation
+            .slerp(self.transforms[(segment + 1) % n].rotation, u.fract());
+        Transform::from_translation(self.position(u)).with_rotation(rotation)
+    }
+}
+
+#[derive(Default)]
+pub struct FlythroughState {
+    spline: Option<CameraSpline>,
+    settled_frames: u32,
+    elapsed: f32,
+    exit_countdown: Option<u32>,
+}
+
+/// Flies the camera through the camera positions once over --flythrough seconds, then exits.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn flythrough(
+    args: Res<Args>,
+    time: Res<Time>,
+    scene: SceneLoadState,
+    camera_positions: Res<CameraPositions>,
+    mut camera: Query<
+        (&mut Transform, Option<&mut CameraController>),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut state: Local<FlythroughState>,
+    mut screenshots: EventWriter<CaptureScreenshot>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    let Some(duration) = args.flythrough else {
+        return;
+    };
+    if let Some(countdown) = &mut state.exit_countdown {
+        if *countdown == 0 {
+            app_exit.send(AppExit);
+        }
+        *countdown = countdown.saturating_sub(1);
+        return;
+    }
+    let Ok((mut transform, controller)) = camera.get_single_mut() else {
+        return;
+    };
+    if state.spline.is_none() {
+        state.settled_frames = if scene.is_loaded() {
+            state.settled_frames + 1
+        } else {
+            0
+        };
+        if state.settled_frames < SCENE_SETTLE_FRAMES {
+            return;
+        }
+        let spline = CameraSpline::closed(&camera_positions);
+        println!(
+            "Starting {duration}s flythrough through {} positions, {:.1}m long",
+            camera_positions.len(),
+            spline.length()
+        );
+        state.spline = Some(spline);
+        if let Some(mut controller) = controller {
+            controller.enabled = false;
+        }
+    }
+
+    let spline = state.spline.as_ref().unwrap();
+    let progress = (state.elapsed / duration).min(1.0);
+    *transform = spline.sample(progress * spline.length());
+    if args.capture {
+        screenshots.send(CaptureScreenshot { position: None });
+    }
+    if progress >= 1.0 {
+        println!("Flythrough finished");
+        state.exit_countdown = Some(EXIT_FRAMES);
+    }
+    state.elapsed += if args.capture {
+        1.0 / CAPTURE_FPS
+    } else {
+        time.delta_seconds()
+    };
+}
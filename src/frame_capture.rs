@@ -0,0 +1,99 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        renderer::RenderDevice,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::benchmark::SceneLoadState;
+
+/// Whether the frame being extracted should be captured, and whether the render world has
+/// captured it
+#[derive(Resource, Clone, ExtractResource)]
+struct FrameCaptureRequest {
+    /// Counted from the first frame the scene is loaded, starting at 1
+    frame: u32,
+    capture: bool,
+    done: Arc<AtomicBool>,
+}
+
+/// With --capture-frame <n>, has a gpu frame debugger attached to the app (RenderDoc, or PIX
+/// through wgpu's RenderDoc hooks) capture the nth frame rendered after the scene is processed
+/// and its mipmaps generated, then exits
+pub struct FrameCapturePlugin {
+    pub frame: u32,
+}
+
+impl Plugin for FrameCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FrameCaptureRequest {
+            frame: self.frame,
+            capture: false,
+            done: Arc::new(AtomicBool::new(false)),
+        })
+        .add_plugins(ExtractResourcePlugin::<FrameCaptureRequest>::default())
+        .add_systems(Update, request_frame_capture);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        // The capture wraps everything the render graph submits for the frame
+        render_app.add_systems(
+            Render,
+            (
+                start_frame_capture
+                    .after(RenderSet::PrepareBindGroups)
+                    .before(RenderSet::Render),
+                stop_frame_capture
+                    .after(RenderSet::Render)
+                    .before(RenderSet::Cleanup),
+            ),
+        );
+    }
+}
+
+fn request_frame_capture(
+    scene: SceneLoadState,
+    mut request: ResMut<FrameCaptureRequest>,
+    mut loaded_frames: Local<u32>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    if request.done.load(Ordering::Acquire) {
+        println!("Captured frame {}, exiting", request.frame);
+        app_exit.send(AppExit);
+        return;
+    }
+    // Changed every frame so the render world always sees the current value
+    request.capture = false;
+    if !scene.is_loaded() || *loaded_frames >= request.frame {
+        return;
+    }
+    *loaded_frames += 1;
+    if *loaded_frames == request.frame {
+        println!("Capturing frame {}", request.frame);
+        request.capture = true;
+    }
+}
+
+fn start_frame_capture(request: Res<FrameCaptureRequest>, render_device: Res<RenderDevice>) {
+    if request.capture {
+        render_device.wgpu_device().start_capture();
+    }
+}
+
+fn stop_frame_capture(request: Res<FrameCaptureRequest>, render_device: Res<RenderDevice>) {
+    if request.capture {
+        render_device.wgpu_device().stop_capture();
+        request.done.store(true, Ordering::Release);
+    }
+}
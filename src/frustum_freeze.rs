@@ -0,0 +1,86 @@
+use bevy::{
+    prelude::*,
+    render::{primitives::Frustum, view::VisibilitySystems},
+};
+
+use crate::{cascade_debug::slice_corners, key_bindings::KeyBindings, minimap::MinimapCamera};
+
+// How far in front of the frozen camera its frustum is drawn
+const FROZEN_FRUSTUM_DRAW_DISTANCE: f32 = 20.0;
+
+/// The main camera's view when it was frozen, culling keeps using it until F is pressed again
+#[derive(Resource, Default)]
+struct FrozenFrustum(Option<(Frustum, GlobalTransform, Projection)>);
+
+/// F: freezes frustum culling where the camera is, so what gets culled from there can be seen
+/// while flying elsewhere. Unlike --no-frustum-culling, culling still happens.
+pub struct FrustumFreezePlugin;
+
+impl Plugin for FrustumFreezePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrozenFrustum>()
+            .add_systems(Update, (toggle_frozen_frustum, draw_frozen_frustum).chain())
+            .add_systems(
+                PostUpdate,
+                apply_frozen_frustum
+                    .after(VisibilitySystems::UpdateProjectionFrusta)
+                    .before(VisibilitySystems::CheckVisibility),
+            );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn toggle_frozen_frustum(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut camera: Query<
+        (&Frustum, &GlobalTransform, &mut Projection),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut frozen: ResMut<FrozenFrustum>,
+) {
+    if !input.just_pressed(keys.freeze_frustum) {
+        return;
+    }
+    let Ok((frustum, transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+    if frozen.0.take().is_some() {
+        // Bevy only updates the frustum when the camera moves, make it catch up
+        projection.set_changed();
+        println!("Frustum culling unfrozen");
+    } else {
+        frozen.0 = Some((*frustum, *transform, projection.clone()));
+        println!("Frustum culling frozen");
+    }
+}
+
+fn draw_frozen_frustum(frozen: Res<FrozenFrustum>, mut gizmos: Gizmos) {
+    let Some((_, transform, projection)) = &frozen.0 else {
+        return;
+    };
+    let near = match projection {
+        Projection::Perspective(perspective) => perspective.near,
+        Projection::Orthographic(orthographic) => orthographic.near,
+    };
+    let near_corners = slice_corners(projection, near).map(|c| transform.transform_point(c));
+    let far_corners = slice_corners(projection, FROZEN_FRUSTUM_DRAW_DISTANCE)
+        .map(|c| transform.transform_point(c));
+    for corners in [near_corners, far_corners] {
+        gizmos.linestrip(corners.into_iter().chain([corners[0]]), Color::CYAN);
+    }
+    for (near_corner, far_corner) in near_corners.into_iter().zip(far_corners) {
+        gizmos.line(near_corner, far_corner, Color::CYAN);
+    }
+}
+
+/// Puts the frozen frustum back after bevy updated it for the moved camera, before culling
+fn apply_frozen_frustum(
+    frozen: Res<FrozenFrustum>,
+    mut camera: Query<&mut Frustum, (With<Camera>, Without<MinimapCamera>)>,
+) {
+    let (Some((frustum, ..)), Ok(mut camera_frustum)) = (&frozen.0, camera.get_single_mut()) else {
+        return;
+    };
+    *camera_frustum = *frustum;
+}
@@ -0,0 +1,265 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use bevy::{
+    core_pipeline::core_3d::graph::{Core3d, Node3d},
+    pbr::graph::NodePbr,
+    prelude::*,
+    render::{
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Maintain, MapMode,
+            WgpuFeatures,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType};
+
+/// Passes that are timed, each one spans from its timestamp to the next.
+pub const GPU_PASSES: [&str; 6] = [
+    "shadows",
+    "prepass/ssao",
+    "main opaque",
+    "main transmissive",
+    "main transparent",
+    "post",
+];
+
+const TIMESTAMP_COUNT: u32 = GPU_PASSES.len() as u32 + 1;
+
+// Timestamps are resolved as u64
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// Gpu time spent in each of [`GPU_PASSES`], summed up since the last [`GpuTimings::reset`].
+/// Shared between the main and render world.
+#[derive(Resource, Clone, Default)]
+pub struct GpuTimings(Arc<Mutex<GpuTimingTotals>>);
+
+#[derive(Default)]
+struct GpuTimingTotals {
+    /// In milliseconds
+    pass_times: [f64; GPU_PASSES.len()],
+    frames: u32,
+}
+
+impl GpuTimings {
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = GpuTimingTotals::default();
+    }
+
+    /// Average time of each pass in milliseconds, `None` if no frame was timed yet.
+    pub fn averages(&self) -> Option<[f32; GPU_PASSES.len()]> {
+        let totals = self.0.lock().unwrap();
+        if totals.frames == 0 {
+            return None;
+        }
+        Some(totals.pass_times.map(|t| (t / totals.frames as f64) as f32))
+    }
+
+    fn add_frame(&self, pass_times: [f64; GPU_PASSES.len()]) {
+        let mut totals = self.0.lock().unwrap();
+        for (total, time) in totals.pass_times.iter_mut().zip(pass_times) {
+            *total += time;
+        }
+        totals.frames += 1;
+    }
+}
+
+/// Times the main 3d render passes with timestamp queries, see [`GpuTimings`].
+/// Does nothing if the gpu doesn't support timestamp queries.
+pub struct GpuTimingPlugin;
+
+impl Plugin for GpuTimingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GpuTimings>();
+    }
+
+    fn finish(&self, app: &mut App) {
+        let timings = app.world.resource::<GpuTimings>().clone();
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        let render_device = render_app.world.resource::<RenderDevice>().clone();
+        if !render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+        {
+            warn!("The gpu doesn't support timestamp queries, --gpu-timing is ignored");
+            return;
+        }
+        let timestamp_period = render_app
+            .world
+            .resource::<RenderQueue>()
+            .get_timestamp_period();
+        render_app
+            .insert_resource(GpuTimestamps::new(
+                &render_device,
+                timestamp_period,
+                timings,
+            ))
+            .add_systems(
+                Render,
+                read_back_timestamps
+                    .after(RenderSet::Render)
+                    .before(RenderSet::Cleanup),
+            );
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        let graph = graph.sub_graph_mut(Core3d);
+        for index in 0..TIMESTAMP_COUNT {
+            graph.add_node(GpuTimestamp(index), TimestampNode { index });
+        }
+        // Pin each timestamp between the passes it separates
+        graph.add_node_edges((GpuTimestamp(0), NodePbr::ShadowPass, GpuTimestamp(1)));
+        graph.add_node_edges((GpuTimestamp(1), Node3d::Prepass));
+        graph.add_node_edges((
+            Node3d::StartMainPass,
+            GpuTimestamp(2),
+            Node3d::MainOpaquePass,
+            GpuTimestamp(3),
+            Node3d::MainTransmissivePass,
+            GpuTimestamp(4),
+            Node3d::MainTransparentPass,
+            GpuTimestamp(5),
+            Node3d::EndMainPass,
+        ));
+        graph.add_node_edges((
+            Node3d::EndMainPassPostProcessing,
+            GpuTimestamp(6),
+            Node3d::Upscaling,
+        ));
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct GpuTimestamp(u32);
+
+#[derive(Resource)]
+struct GpuTimestamps {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    /// Whether `readback_buffer` is waiting to be mapped, it can't be copied to until it's read.
+    mapping: bool,
+    mapped: Arc<AtomicBool>,
+    timings: GpuTimings,
+}
+
+impl GpuTimestamps {
+    fn new(render_device: &RenderDevice, timestamp_period: f32, timings: GpuTimings) -> Self {
+        let size = TIMESTAMP_COUNT as u64 * TIMESTAMP_SIZE;
+        Self {
+            query_set: render_device
+                .wgpu_device()
+                .create_query_set(&QuerySetDescriptor {
+                    label: Some("gpu_timing_query_set"),
+                    ty: QueryType::Timestamp,
+                    count: TIMESTAMP_COUNT,
+                }),
+            resolve_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("gpu_timing_resolve_buffer"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("gpu_timing_readback_buffer"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            timestamp_period,
+            mapping: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+            timings,
+        }
+    }
+}
+
+struct TimestampNode {
+    index: u32,
+}
+
+impl Node for TimestampNode {
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        if let Some(timestamps) = world.get_resource::<GpuTimestamps>() {
+            render_context
+                .command_encoder()
+                .write_timestamp(&timestamps.query_set, self.index);
+        }
+        Ok(())
+    }
+}
+
+/// Copies this frame's timestamps to the readback buffer if the last ones were read already.
+/// The buffer is mapped asynchronously so this never waits on the gpu.
+fn read_back_timestamps(
+    mut timestamps: ResMut<GpuTimestamps>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    render_device.poll(Maintain::Poll);
+    if timestamps.mapping {
+        if !timestamps.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let ticks = {
+            let slice = timestamps.readback_buffer.slice(..);
+            let data = slice.get_mapped_range();
+            data.chunks_exact(TIMESTAMP_SIZE as usize)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        timestamps.readback_buffer.unmap();
+        timestamps.mapping = false;
+        // Timestamps that weren't written this frame (e.g. no camera rendered) read as 0
+        if ticks.windows(2).all(|w| w[0] > 0 && w[1] >= w[0]) {
+            let period = timestamps.timestamp_period as f64;
+            let mut pass_times = [0.0; GPU_PASSES.len()];
+            for (time, w) in pass_times.iter_mut().zip(ticks.windows(2)) {
+                *time = (w[1] - w[0]) as f64 * period / 1_000_000.0;
+            }
+            timestamps.timings.add_frame(pass_times);
+        }
+    }
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("gpu_timing_resolve"),
+    });
+    encoder.resolve_query_set(
+        &timestamps.query_set,
+        0..TIMESTAMP_COUNT,
+        &timestamps.resolve_buffer,
+        0,
+    );
+    encoder.copy_buffer_to_buffer(
+        &timestamps.resolve_buffer,
+        0,
+        &timestamps.readback_buffer,
+        0,
+        TIMESTAMP_COUNT as u64 * TIMESTAMP_SIZE,
+    );
+    render_queue.submit([encoder.finish()]);
+
+    let mapped = timestamps.mapped.clone();
+    render_device.map_buffer(
+        &timestamps.readback_buffer.slice(..),
+        MapMode::Read,
+        move |result| match result {
+            Ok(()) => mapped.store(true, Ordering::Release),
+            Err(e) => warn!("Failed to map gpu timing buffer: {e}"),
+        },
+    );
+    timestamps.mapping = true;
+}
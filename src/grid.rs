@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use crate::{benchmark::BenchmarkRunning, key_bindings::KeyBindings, minimap::MinimapCamera};
+
+// Spacing of the thin and thick grid lines, in meters
+const GRID_MINOR_SPACING: f32 = 1.0;
+const GRID_MAJOR_SPACING: f32 = 10.0;
+// How far the grid reaches from the camera in x and z, in meters. It moves with the camera in
+// whole major cells, so it looks endless.
+const GRID_EXTENT: f32 = 60.0;
+const GRID_MINOR_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 0.25);
+const GRID_MAJOR_COLOR: Color = Color::rgba(0.7, 0.7, 0.7, 0.6);
+// Lines along the x and z axes
+const GRID_X_AXIS_COLOR: Color = Color::rgb(0.9, 0.2, 0.2);
+const GRID_Z_AXIS_COLOR: Color = Color::rgb(0.2, 0.4, 0.9);
+
+/// Gizmos of the grid, pulled slightly towards the camera so they don't z-fight with a floor at
+/// y=0
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct GridGizmos;
+
+/// Whether the grid is shown, toggled with backslash. It's always hidden while benchmarking.
+#[derive(Resource)]
+pub struct GridVisible(pub bool);
+
+/// Reference grid on the y=0 plane around the camera, shown from the start with --grid
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_gizmo_group(
+            GridGizmos,
+            GizmoConfig {
+                line_width: 1.0,
+                depth_bias: -0.001,
+                ..default()
+            },
+        )
+        .add_systems(Update, (toggle_grid, draw_grid).chain());
+    }
+}
+
+fn toggle_grid(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut visible: ResMut<GridVisible>,
+) {
+    if input.just_pressed(keys.toggle_grid) {
+        visible.0 = !visible.0;
+    }
+}
+
+fn draw_grid(
+    visible: Res<GridVisible>,
+    benchmark: Res<BenchmarkRunning>,
+    camera: Query<&GlobalTransform, (With<Camera>, Without<MinimapCamera>)>,
+    mut gizmos: Gizmos<GridGizmos>,
+) {
+    if !visible.0 || benchmark.0 {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let snap = |v: f32| (v / GRID_MAJOR_SPACING).round() * GRID_MAJOR_SPACING;
+    let center = Vec2::new(snap(camera.translation().x), snap(camera.translation().z));
+    let min = center - GRID_EXTENT;
+    let max = center + GRID_EXTENT;
+
+    let lines = (GRID_EXTENT * 2.0 / GRID_MINOR_SPACING).round() as i32;
+    for i in 0..=lines {
+        let offset = -GRID_EXTENT + i as f32 * GRID_MINOR_SPACING;
+        let major = (offset / GRID_MAJOR_SPACING).fract().abs() < 1e-4;
+        let color = |coordinate: f32, axis_color| {
+            if coordinate.abs() < 1e-4 {
+                axis_color
+            } else if major {
+                GRID_MAJOR_COLOR
+            } else {
+                GRID_MINOR_COLOR
+            }
+        };
+        // Line of constant x, running along the z axis
+        let x = center.x + offset;
+        let start = Vec3::new(x, 0.0, min.y);
+        gizmos.line(start, Vec3::new(x, 0.0, max.y), color(x, GRID_Z_AXIS_COLOR));
+        // Line of constant z, running along the x axis
+        let z = center.y + offset;
+        let start = Vec3::new(min.x, 0.0, z);
+        gizmos.line(start, Vec3::new(max.x, 0.0, z), color(z, GRID_X_AXIS_COLOR));
+    }
+}
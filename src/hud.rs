@@ -0,0 +1,110 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    ecs::entity::Entities,
+    prelude::*,
+};
+
+use crate::{benchmark::BenchmarkRunning, key_bindings::KeyBindings, mesh_lod::LodStats};
+
+/// Marks the text of the diagnostics overlay
+#[derive(Component)]
+pub struct Hud;
+
+/// Whether the overlay is shown, toggled with F1. It's always hidden while benchmarking.
+#[derive(Resource)]
+pub struct HudVisible(pub bool);
+
+/// On screen fps, frame time and entity counts, enabled with --hud
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HudVisible(true))
+            .add_systems(Startup, spawn_hud)
+            .add_systems(Update, (toggle_hud, update_hud).chain());
+    }
+}
+
+fn spawn_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        Hud,
+    ));
+}
+
+fn toggle_hud(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut visible: ResMut<HudVisible>,
+    benchmark: Res<BenchmarkRunning>,
+    mut hud: Query<&mut Visibility, With<Hud>>,
+) {
+    if input.just_pressed(keys.toggle_hud) {
+        visible.0 = !visible.0;
+    }
+    let visibility = if visible.0 && !benchmark.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for mut hud_visibility in &mut hud {
+        hud_visibility.set_if_neq(visibility);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_hud(
+    visible: Res<HudVisible>,
+    benchmark: Res<BenchmarkRunning>,
+    diagnostics: Res<DiagnosticsStore>,
+    entities: &Entities,
+    mesh_entities: Query<(), With<Handle<Mesh>>>,
+    meshes: Res<Assets<Mesh>>,
+    lod_stats: Option<Res<LodStats>>,
+    mut hud: Query<&mut Text, With<Hud>>,
+) {
+    if !visible.0 || benchmark.0 {
+        return;
+    }
+    let smoothed = |path| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or_default()
+    };
+    let mut text = format!(
+        "{:.0} fps, {:.2}ms\n{} entities, {} mesh entities, {} meshes",
+        smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+        smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        entities.len(),
+        mesh_entities.iter().count(),
+        meshes.len()
+    );
+    // With --lod
+    if let Some(stats) = lod_stats.filter(|stats| stats.full > 0) {
+        text += &format!(
+            "\n{} of {} visible triangles drawn with LODs ({:.0}% fewer)",
+            stats.drawn,
+            stats.full,
+            (1.0 - stats.drawn as f32 / stats.full as f32) * 100.0
+        );
+    }
+    for mut hud in &mut hud {
+        hud.sections[0].value.clone_from(&text);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::camera_controller::CameraController;
+
+/// The keys of every action, loaded from `--keymap <path>`. Actions missing from the file keep
+/// their default key, e.g. `(benchmark: Numpad0)`. No two actions can share a key.
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub benchmark: KeyCode,
+    pub screenshot: Vec<KeyCode>,
+    /// Starts a capture with --ss-capture
+    pub supersampled_capture: KeyCode,
+    pub exposure_down: KeyCode,
+    pub exposure_up: KeyCode,
+    pub toggle_sun_shadows: KeyCode,
+    /// Prints the camera transform and records it for `save_cam_positions`
+    pub print_transform: KeyCode,
+    pub copy_transform: KeyCode,
+    pub save_cam_positions: KeyCode,
+    /// Moves to the camera position after the last one selected
+    pub next_cam_pos: KeyCode,
+    /// Move to the first, second... camera position
+    pub cam_positions: Vec<KeyCode>,
+
+    pub forward: KeyCode,
+    pub back: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub run: KeyCode,
+    pub enable_mouse: KeyCode,
+    pub toggle_orbit: KeyCode,
+    pub roll_left: KeyCode,
+    pub roll_right: KeyCode,
+
+    pub toggle_hud: KeyCode,
+    pub toggle_wireframe: KeyCode,
+    pub toggle_bloom: KeyCode,
+    pub toggle_ssao: KeyCode,
+    pub toggle_aa: KeyCode,
+    pub toggle_env_map: KeyCode,
+    pub toggle_projection: KeyCode,
+    pub cycle_msaa: KeyCode,
+    pub cycle_tonemapping: KeyCode,
+    pub cycle_ssao_quality: KeyCode,
+    pub bloom_down: KeyCode,
+    pub bloom_up: KeyCode,
+    pub shadow_depth_bias_down: KeyCode,
+    pub shadow_depth_bias_up: KeyCode,
+    pub shadow_normal_bias_down: KeyCode,
+    pub shadow_normal_bias_up: KeyCode,
+    /// Held to rotate the sun
+    pub sun_down: KeyCode,
+    pub sun_up: KeyCode,
+    pub bounce_lights_down: KeyCode,
+    pub bounce_lights_up: KeyCode,
+    pub pause: KeyCode,
+    pub time_slower: KeyCode,
+    pub time_faster: KeyCode,
+    pub reload_scene: KeyCode,
+    pub freeze_frustum: KeyCode,
+    pub freeze_cascades: KeyCode,
+    pub draw_call_stats: KeyCode,
+    pub material_stats: KeyCode,
+    pub toggle_grid: KeyCode,
+    pub toggle_debug_colors: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            benchmark: KeyCode::KeyB,
+            screenshot: vec![KeyCode::F12, KeyCode::PrintScreen],
+            supersampled_capture: KeyCode::F10,
+            exposure_down: KeyCode::Minus,
+            exposure_up: KeyCode::Equal,
+            toggle_sun_shadows: KeyCode::F2,
+            print_transform: KeyCode::KeyI,
+            copy_transform: KeyCode::KeyC,
+            save_cam_positions: KeyCode::KeyO,
+            next_cam_pos: KeyCode::KeyN,
+            cam_positions: vec![
+                KeyCode::Digit1,
+                KeyCode::Digit2,
+                KeyCode::Digit3,
+                KeyCode::Digit4,
+                KeyCode::Digit5,
+                KeyCode::Digit6,
+                KeyCode::Digit7,
+                KeyCode::Digit8,
+                KeyCode::Digit9,
+            ],
+
+            forward: KeyCode::KeyW,
+            back: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            up: KeyCode::KeyE,
+            down: KeyCode::KeyQ,
+            run: KeyCode::ShiftLeft,
+            enable_mouse: KeyCode::KeyM,
+            toggle_orbit: KeyCode::F3,
+            roll_left: KeyCode::ArrowLeft,
+            roll_right: KeyCode::ArrowRight,
+
+            toggle_hud: KeyCode::F1,
+            toggle_wireframe: KeyCode::F4,
+            toggle_bloom: KeyCode::F5,
+            toggle_ssao: KeyCode::F6,
+            toggle_aa: KeyCode::F7,
+            toggle_env_map: KeyCode::F8,
+            toggle_projection: KeyCode::F9,
+            cycle_msaa: KeyCode::F11,
+            cycle_tonemapping: KeyCode::KeyT,
+            cycle_ssao_quality: KeyCode::KeyL,
+            bloom_down: KeyCode::Semicolon,
+            bloom_up: KeyCode::Quote,
+            shadow_depth_bias_down: KeyCode::KeyY,
+            shadow_depth_bias_up: KeyCode::KeyU,
+            shadow_normal_bias_down: KeyCode::KeyG,
+            shadow_normal_bias_up: KeyCode::KeyH,
+            sun_down: KeyCode::BracketLeft,
+            sun_up: KeyCode::BracketRight,
+            bounce_lights_down: KeyCode::Comma,
+            bounce_lights_up: KeyCode::Period,
+            pause: KeyCode::KeyP,
+            time_slower: KeyCode::KeyZ,
+            time_faster: KeyCode::KeyX,
+            reload_scene: KeyCode::KeyR,
+            freeze_frustum: KeyCode::KeyF,
+            freeze_cascades: KeyCode::KeyV,
+            draw_call_stats: KeyCode::KeyJ,
+            material_stats: KeyCode::KeyK,
+            toggle_grid: KeyCode::Backslash,
+            toggle_debug_colors: KeyCode::Slash,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keymap {}", path.display()))?;
+        let bindings: Self = ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse keymap {}", path.display()))?;
+        bindings
+            .check_duplicates()
+            .with_context(|| format!("Invalid keymap {}", path.display()))?;
+        Ok(bindings)
+    }
+
+    /// Gives the camera controller the movement keys of this keymap
+    pub fn apply_to(&self, controller: &mut CameraController) {
+        controller.key_forward = self.forward;
+        controller.key_back = self.back;
+        controller.key_left = self.left;
+        controller.key_right = self.right;
+        controller.key_up = self.up;
+        controller.key_down = self.down;
+        controller.key_run = self.run;
+        controller.keyboard_key_enable_mouse = self.enable_mouse;
+        controller.key_toggle_orbit = self.toggle_orbit;
+        controller.key_roll_left = self.roll_left;
+        controller.key_roll_right = self.roll_right;
+    }
+
+    fn check_duplicates(&self) -> anyhow::Result<()> {
+        let mut actions = HashMap::new();
+        for (action, key) in self.actions() {
+            match actions.insert(key, action) {
+                Some(other) if other == action => bail!("{action} lists {key:?} twice"),
+                Some(other) => bail!("{other} and {action} are both bound to {key:?}"),
+                None => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn actions(&self) -> Vec<(&'static str, KeyCode)> {
+        let mut actions = vec![
+            ("benchmark", self.benchmark),
+            ("supersampled_capture", self.supersampled_capture),
+            ("exposure_down", self.exposure_down),
+            ("exposure_up", self.exposure_up),
+            ("toggle_sun_shadows", self.toggle_sun_shadows),
+            ("print_transform", self.print_transform),
+            ("copy_transform", self.copy_transform),
+            ("save_cam_positions", self.save_cam_positions),
+            ("next_cam_pos", self.next_cam_pos),
+            ("forward", self.forward),
+            ("back", self.back),
+            ("left", self.left),
+            ("right", self.right),
+            ("up", self.up),
+            ("down", self.down),
+            ("run", self.run),
+            ("enable_mouse", self.enable_mouse),
+            ("toggle_orbit", self.toggle_orbit),
+            ("roll_left", self.roll_left),
+            ("roll_right", self.roll_right),
+            ("toggle_hud", self.toggle_hud),
+            ("toggle_wireframe", self.toggle_wireframe),
+            ("toggle_bloom", self.toggle_bloom),
+            ("toggle_ssao", self.toggle_ssao),
+            ("toggle_aa", self.toggle_aa),
+            ("toggle_env_map", self.toggle_env_map),
+            ("toggle_projection", self.toggle_projection),
+            ("cycle_msaa", self.cycle_msaa),
+            ("cycle_tonemapping", self.cycle_tonemapping),
+            ("cycle_ssao_quality", self.cycle_ssao_quality),
+            ("bloom_down", self.bloom_down),
+            ("bloom_up", self.bloom_up),
+            ("shadow_depth_bias_down", self.shadow_depth_bias_down),
+            ("shadow_depth_bias_up", self.shadow_depth_bias_up),
+            ("shadow_normal_bias_down", self.shadow_normal_bias_down),
+            ("shadow_normal_bias_up", self.shadow_normal_bias_up),
+            ("sun_down", self.sun_down),
+            ("sun_up", self.sun_up),
+            ("bounce_lights_down", self.bounce_lights_down),
+            ("bounce_lights_up", self.bounce_lights_up),
+            ("pause", self.pause),
+            ("time_slower", self.time_slower),
+            ("time_faster", self.time_faster),
+            ("reload_scene", self.reload_scene),
+            ("freeze_frustum", self.freeze_frustum),
+            ("freeze_cascades", self.freeze_cascades),
+            ("draw_call_stats", self.draw_call_stats),
+            ("material_stats", self.material_stats),
+            ("toggle_grid", self.toggle_grid),
+            ("toggle_debug_colors", self.toggle_debug_colors),
+        ];
+        actions.extend(self.screenshot.iter().map(|&key| ("screenshot", key)));
+        actions.extend(self.cam_positions.iter().map(|&key| ("cam_positions", key)));
+        actions
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use bevy::{core::FrameCount, prelude::*};
+
+use crate::{benchmark::SceneLoadState, mipmap_generator::MipmapProgress, scene_bounds::SceneRoot};
+
+/// When each part of loading the scene finished, measured from the start of the load
+#[derive(Default)]
+pub struct LoadTimes {
+    /// The scene root being timed, a new one after a reload starts over
+    root: Option<Entity>,
+    started: Option<Instant>,
+    /// Images already counted in [`MipmapProgress`] before this load
+    mipmaps_before: u32,
+    gltf: Option<Duration>,
+    mipmaps: Option<Duration>,
+    proc_scene: Option<Duration>,
+    /// The frame on which the gltf, mipmaps and proc_scene were all done
+    finished_frame: Option<u32>,
+    reported: bool,
+}
+
+/// Prints how long the gltf took to load, and how long mipmap generation and [`crate::proc_scene`]
+/// took after that, once the first frame with the whole scene has been rendered.
+pub fn report_load_timing(
+    real_time: Res<Time<Real>>,
+    asset_server: Res<AssetServer>,
+    roots: Query<(Entity, &Handle<Scene>), With<SceneRoot>>,
+    scene: SceneLoadState,
+    mipmaps: Res<MipmapProgress>,
+    frame: Res<FrameCount>,
+    mut times: Local<LoadTimes>,
+) {
+    let Some((root, _)) = roots.iter().min_by_key(|(entity, _)| *entity) else {
+        return;
+    };
+    if times.root != Some(root) {
+        let started = if times.root.is_none() {
+            // The first scene starts loading in setup
+            real_time.startup()
+        } else {
+            Instant::now()
+        };
+        *times = LoadTimes {
+            root: Some(root),
+            started: Some(started),
+            mipmaps_before: mipmaps.total,
+            ..default()
+        };
+    }
+    if times.reported {
+        return;
+    }
+    let elapsed = times.started.unwrap().elapsed();
+
+    if times.gltf.is_none()
+        && roots
+            .iter()
+            .all(|(_, handle)| asset_server.is_loaded_with_dependencies(handle))
+    {
+        times.gltf = Some(elapsed);
+    }
+    if times.proc_scene.is_none() && scene.is_processed() {
+        times.proc_scene = Some(elapsed);
+    }
+    // `processed` also catches up with `total` between batches of queued images, only count the
+    // mipmaps as done once no task is left
+    let mipmaps_done = mipmaps.total > times.mipmaps_before
+        && mipmaps.processed == mipmaps.total
+        && scene.mipmaps_generated();
+    // Without any images to generate mips for, they are done with the rest of the scene
+    if times.mipmaps.is_none() && (mipmaps_done || scene.is_loaded()) {
+        times.mipmaps = times.gltf.map(|_| elapsed);
+    }
+
+    let (Some(gltf), Some(mipmaps), Some(proc_scene)) =
+        (times.gltf, times.mipmaps, times.proc_scene)
+    else {
+        return;
+    };
+    // Report on the frame after, the first one rendered with everything loaded
+    let finished_frame = *times.finished_frame.get_or_insert(frame.0);
+    if frame.0 == finished_frame {
+        return;
+    }
+    times.reported = true;
+    println!(
+        "Load timing: gltf load {:.2}s, mipmaps {:.2}s, post-proc {:.2}s after the gltf, first frame at {:.2}s",
+        gltf.as_secs_f32(),
+        mipmaps.saturating_sub(gltf).as_secs_f32(),
+        proc_scene.saturating_sub(gltf).as_secs_f32(),
+        elapsed.as_secs_f32()
+    );
+}
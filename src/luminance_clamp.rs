@@ -0,0 +1,197 @@
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex, ExtractComponentPlugin, UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraph, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode,
+            ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{texture_2d, uniform_buffer},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CachedRenderPipelineId,
+            ColorTargetState, ColorWrites, FragmentState, MultisampleState, Operations,
+            PipelineCache, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, ShaderStages, TextureSampleType,
+        },
+        renderer::{RenderContext, RenderDevice},
+        view::ViewTarget,
+        RenderApp,
+    },
+};
+
+const LUMINANCE_CLAMP_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(84726351909264417);
+
+pub use settings::LuminanceClamp;
+
+// The ShaderType derive generates a check function next to the struct that's never called, an
+// allow on the struct itself doesn't reach it
+#[allow(dead_code)]
+mod settings {
+    use bevy::{
+        prelude::*,
+        render::{extract_component::ExtractComponent, render_resource::ShaderType},
+    };
+
+    /// Caps the luminance of every pixel of the camera's hdr image at `max_luminance` before taa
+    /// and bloom, in the units of the exposed image where 1.0 is about white after tonemapping.
+    /// Stops single very bright pixels from flickering under taa and blooming into fireflies, at
+    /// the cost of highlights above the cap losing their brightness, so they bloom less.
+    #[derive(Component, Clone, Copy, ExtractComponent, ShaderType)]
+    pub struct LuminanceClamp {
+        pub max_luminance: f32,
+    }
+}
+
+/// With --clamp-luminance, runs [`LuminanceClamp`] on the cameras that have it
+pub struct LuminanceClampPlugin;
+
+impl Plugin for LuminanceClampPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            LUMINANCE_CLAMP_SHADER_HANDLE,
+            "luminance_clamp.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins((
+            ExtractComponentPlugin::<LuminanceClamp>::default(),
+            UniformComponentPlugin::<LuminanceClamp>::default(),
+        ));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        // Only there with --aa taa
+        let has_taa = render_app
+            .world
+            .resource::<RenderGraph>()
+            .get_sub_graph(Core3d)
+            .is_some_and(|graph| graph.get_node_state(Node3d::Taa).is_ok());
+        render_app
+            .init_resource::<LuminanceClampPipeline>()
+            .add_render_graph_node::<ViewNodeRunner<LuminanceClampNode>>(
+                Core3d,
+                LuminanceClampLabel,
+            )
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::EndMainPass, LuminanceClampLabel, Node3d::Bloom),
+            );
+        if has_taa {
+            render_app.add_render_graph_edges(Core3d, (LuminanceClampLabel, Node3d::Taa));
+        }
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct LuminanceClampLabel;
+
+#[derive(Default)]
+struct LuminanceClampNode;
+
+impl ViewNode for LuminanceClampNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static DynamicUniformIndex<LuminanceClamp>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, settings_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let clamp_pipeline = world.resource::<LuminanceClampPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let (Some(pipeline), Some(settings)) = (
+            pipeline_cache.get_render_pipeline(clamp_pipeline.pipeline_id),
+            world
+                .resource::<ComponentUniforms<LuminanceClamp>>()
+                .uniforms()
+                .binding(),
+        ) else {
+            return Ok(());
+        };
+
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context.render_device().create_bind_group(
+            "luminance_clamp_bind_group",
+            &clamp_pipeline.layout,
+            &BindGroupEntries::sequential((post_process.source, settings)),
+        );
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("luminance_clamp_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: post_process.destination,
+                resolve_target: None,
+                ops: Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[settings_index.index()]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct LuminanceClampPipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for LuminanceClampPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let layout = world.resource::<RenderDevice>().create_bind_group_layout(
+            "luminance_clamp_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    uniform_buffer::<LuminanceClamp>(true),
+                ),
+            ),
+        );
+        // --clamp-luminance requires hdr, so the image is always in the hdr format
+        let pipeline_id =
+            world
+                .resource_mut::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("luminance_clamp_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader: LUMINANCE_CLAMP_SHADER_HANDLE,
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        targets: vec![Some(ColorTargetState {
+                            format: ViewTarget::TEXTURE_FORMAT_HDR,
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                });
+        Self {
+            layout,
+            pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct LuminanceClamp {
+    max_luminance: f32,
+}
+
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> settings: LuminanceClamp;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(screen_texture, vec2<i32>(in.position.xy), 0);
+    let luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    // The whole color is scaled down so its hue stays the same
+    let scale = min(1.0, settings.max_luminance / max(luminance, 1e-6));
+    return vec4(color.rgb * scale, color.a);
+}
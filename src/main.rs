@@ -1,44 +1,219 @@
-use std::{f32::consts::PI, time::Instant};
+use std::{
+    f32::consts::PI,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
+mod asset_check;
+mod auto_exposure;
 mod auto_instance;
+mod bench_compare;
+mod bench_sweep;
+mod benchmark;
 mod camera_controller;
+mod camera_positions;
+mod cascade_debug;
+mod config;
+mod debug_material_colors;
+mod diag_file;
+mod export_gltf;
+mod flythrough;
+mod frame_capture;
+mod frustum_freeze;
+mod gpu_timing;
+mod grid;
+mod hud;
+mod key_bindings;
+mod load_timing;
+mod luminance_clamp;
+mod material_overrides;
+mod mesh_lod;
+mod mesh_optimizer;
+mod minimap;
 mod mipmap_generator;
+mod scene_bounds;
+mod scene_reload;
+mod screenshot;
+mod session;
+mod stats;
+mod texture_budget;
+mod watchdog;
 
 use argh::FromArgs;
+use asset_check::{check_watched_assets, WatchedAssets};
+use auto_exposure::{AutoExposure, AutoExposurePlugin};
 use auto_instance::{
     consolidate_material_instances, AutoInstanceMaterialPlugin, AutoInstancePlugin,
 };
+use bench_compare::compare_bench_json;
+use bench_sweep::{apply_bench_sweep, parse_bench_sweep, BenchSweep, SweepSpec};
+use benchmark::{benchmark, BenchmarkRunning};
 use bevy::{
+    app::ScheduleRunnerPlugin,
     core_pipeline::{
-        bloom::BloomSettings,
-        experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasPlugin},
+        bloom::{BloomPrefilterSettings, BloomSettings},
+        experimental::taa::{
+            TemporalAntiAliasBundle, TemporalAntiAliasPlugin, TemporalAntiAliasSettings,
+        },
+        fxaa::Fxaa,
+        tonemapping::Tonemapping,
     },
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    ecs::schedule::ExecutorKind,
     pbr::{
-        CascadeShadowConfigBuilder, ScreenSpaceAmbientOcclusionBundle, TransmittedShadowReceiver,
+        wireframe::{Wireframe, WireframePlugin},
+        CascadeShadowConfig, CascadeShadowConfigBuilder, DirectionalLightShadowMap,
+        ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionQualityLevel,
+        ScreenSpaceAmbientOcclusionSettings, TransmittedShadowReceiver,
     },
     prelude::*,
-    render::view::{ColorGrading, NoFrustumCulling},
-    window::{PresentMode, WindowResolution},
-    winit::{UpdateMode, WinitSettings},
+    render::{
+        camera::{RenderTarget, ScalingMode, TemporalJitter},
+        pipelined_rendering::PipelinedRenderingPlugin,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        renderer::RenderAdapter,
+        view::{ColorGrading, NoFrustumCulling, ViewTarget},
+        RenderApp,
+    },
+    utils::HashMap,
+    window::{ExitCondition, PresentMode, WindowResolution},
+    winit::{UpdateMode, WinitPlugin, WinitSettings},
 };
 use camera_controller::{CameraController, CameraControllerPlugin};
-use mipmap_generator::{generate_mipmaps, MipmapGeneratorPlugin, MipmapGeneratorSettings};
+use camera_positions::{
+    move_camera_to, transform_literal, tween_camera, watch_camera_positions, CameraPositions,
+    CameraTween, Clipboard, RecordedCameraPositions, RECORDED_CAMERA_POSITIONS_PATH,
+};
+use cascade_debug::debug_cascades;
+use debug_material_colors::{toggle_debug_material_colors, DebugMaterialColors};
+use diag_file::{write_diag_file, DiagFile};
+use export_gltf::export_gltf;
+use flythrough::flythrough;
+use frame_capture::FrameCapturePlugin;
+use frustum_freeze::FrustumFreezePlugin;
+use gpu_timing::GpuTimingPlugin;
+use grid::{GridPlugin, GridVisible};
+use hud::HudPlugin;
+use key_bindings::KeyBindings;
+use load_timing::report_load_timing;
+use luminance_clamp::{LuminanceClamp, LuminanceClampPlugin};
+use material_overrides::MaterialOverrides;
+use mesh_lod::MeshLodPlugin;
+use mesh_optimizer::optimize_meshes;
+use minimap::{MinimapCamera, MinimapPlugin};
+use mipmap_generator::{
+    generate_mipmaps, MipmapGeneratorPlugin, MipmapGeneratorSettings, MipmapTasks,
+};
+use scene_bounds::{compute_scene_bounds, SceneBounds, SceneRoot};
+use scene_reload::reload_scene;
+use screenshot::{CapturePlugin, CaptureScreenshot};
+use session::{save_session_on_exit, PostEffects, Session};
+use stats::{check_materials, draw_call_stats, dump_materials, material_stats};
+use texture_budget::{apply_texture_budget, hold_textures, HeldTextures};
+use watchdog::{watchdog_heartbeat, LoadPhase, Watchdog};
 
 use crate::{
     auto_instance::{AutoInstanceMaterialRecursive, AutoInstanceMeshRecursive},
-    convert::{change_gltf_to_use_ktx2, convert_images_to_ktx2},
+    convert::{
+        change_gltf_to_use_ktx2, convert_images_to_ktx2, find_gltf_files, CompressionFormat,
+        CompressionSpeed, DEFAULT_CONVERT_DIR,
+    },
+    validate::validate_ktx2_images,
 };
 
 mod convert;
+mod validate;
 
 #[derive(FromArgs, Resource, Clone)]
 /// Config
 pub struct Args {
+    /// toml file with defaults for any of these flags, e.g. `fov = 70.0`, flags given here win
+    #[argh(option)]
+    config: Option<String>,
+
     /// convert gltf to use ktx
     #[argh(switch)]
     convert: bool,
 
+    /// directory with the gltf and png textures to convert (default: assets/san-miguel)
+    #[argh(option)]
+    convert_src: Option<String>,
+
+    /// directory to write the converted gltf and ktx2 textures to (default: in place)
+    #[argh(option)]
+    convert_dst: Option<String>,
+
+    /// maximum number of images converted at once (default: number of cores)
+    #[argh(option)]
+    convert_threads: Option<usize>,
+
+    /// debugging aid: run bevy's task pools, systems, rendering and --convert on a single
+    /// thread, so loading happens in the same order every run and panics are easier to follow
+    #[argh(switch)]
+    single_thread: bool,
+
+    /// warn when no frame has completed for this many seconds, with what the app was doing
+    #[argh(option)]
+    watchdog: Option<f32>,
+
+    /// exit with an error when the --watchdog fires, for unattended runs
+    #[argh(switch)]
+    watchdog_exit: bool,
+
+    /// only print the files and texture uris that --convert would change
+    #[argh(switch)]
+    convert_dry_run: bool,
+
+    /// ktx2 format for color textures: bc7, bc5, bc3, bc1 or astc (default: bc7)
+    #[argh(option)]
+    compression_format: Option<CompressionFormat>,
+
+    /// ktx2 encoding speed: ultrafast, fast, normal or slow, slower is higher quality (default: normal)
+    #[argh(option, default = "CompressionSpeed::Normal")]
+    compression_speed: CompressionSpeed,
+
+    /// write the converted ktx2 textures without zstd supercompression
+    #[argh(switch)]
+    no_zstd: bool,
+
+    /// zstd level the converted ktx2 textures are supercompressed with (default: 0, kram's
+    /// default level)
+    #[argh(option)]
+    zstd_level: Option<i32>,
+
+    /// compare the converted ktx2 textures with their png sources and exit
+    #[argh(switch)]
+    validate: bool,
+
+    /// gltf or glb file to load, relative to the assets directory, optionally followed by a
+    /// #Scene<n> label (default: san-miguel/san-miguel.gltf)
+    #[argh(option, default = "String::from(DEFAULT_SCENE)")]
+    scene: String,
+
+    /// move the camera to show the whole scene once it has loaded, for models other than San Miguel
+    #[argh(switch)]
+    auto_frame: bool,
+
+    /// show a top down map of the scene with the camera position in the corner
+    #[argh(switch)]
+    minimap: bool,
+
+    /// keep the lights in the gltf instead of replacing them with the San Miguel sun and bounce lights
+    #[argh(switch)]
+    keep_scene_lights: bool,
+
+    /// neutral lighting for reviewing materials: a white directional light without shadows and
+    /// a flat white ambient light instead of the sun, spot lights and environment map
+    #[argh(switch)]
+    review_lighting: bool,
+
+    /// spawn the scene this many times in a grid to test how rendering scales, the copies
+    /// share their meshes and materials (default: 1)
+    #[argh(option, default = "1")]
+    scene_instances: u32,
+
     /// enable auto instancing for meshes/materials
     #[argh(switch)]
     instance: bool,
@@ -47,80 +222,1073 @@ pub struct Args {
     #[argh(switch)]
     minimal: bool,
 
-    /// whether to disable frustum culling.
+    /// render to an 8 bit target instead of hdr, for gpus that render the scene wrong in hdr.
+    /// Bloom needs hdr so it's disabled
+    #[argh(switch)]
+    no_hdr: bool,
+
+    /// cap each pixel's luminance at this before taa and bloom, in exposed units where 1.0 is
+    /// about white, to stop bright specks from sparkling. Highlights above it lose brightness
+    /// and bloom less
+    #[argh(option)]
+    clamp_luminance: Option<f32>,
+
+    /// whether to disable frustum culling. Press F instead to freeze culling where the camera is
     #[argh(switch)]
     no_frustum_culling: bool,
 
+    /// show a reference grid on the y=0 plane, toggle it with backslash
+    #[argh(switch)]
+    grid: bool,
+
     /// run at 720p (this scene is easily GPU limited)
     #[argh(switch)]
     p720: bool,
+
+    /// render width in pixels, requires --height and takes precedence over --p720
+    #[argh(option)]
+    width: Option<u32>,
+
+    /// render height in pixels, requires --width and takes precedence over --p720
+    #[argh(option)]
+    height: Option<u32>,
+
+    /// camera walk speed (run speed is scaled to match)
+    #[argh(option)]
+    cam_speed: Option<f32>,
+
+    /// camera mouse look sensitivity
+    #[argh(option)]
+    cam_sensitivity: Option<f32>,
+
+    /// vertical field of view in degrees, switch to orthographic and back with F9 (default: 60.0)
+    #[argh(option, default = "60.0")]
+    fov: f32,
+
+    /// limit camera roll (left and right arrow keys) to +-this many degrees, unlimited by default
+    #[argh(option)]
+    cam_max_roll: Option<f32>,
+
+    /// point the camera orbits around in orbit mode (F3), as x,y,z (default: 0,3.5,0)
+    #[argh(option, default = "SCENE_CENTER", from_str_fn(parse_vec3))]
+    orbit_pivot: Vec3,
+
+    /// ron file with a list of camera transforms, bound to keys 1-9 and N for the next one,
+    /// reloaded when it changes
+    #[argh(option)]
+    cam_positions: Option<String>,
+
+    /// animate between camera positions over this many seconds instead of teleporting
+    #[argh(option)]
+    cam_smooth: Option<f32>,
+
+    /// run the benchmark without a window once the scene has loaded, then exit
+    #[argh(switch)]
+    bench_headless: bool,
+
+    /// append benchmark results to this csv file
+    #[argh(option)]
+    bench_csv: Option<String>,
+
+    /// write the benchmark results and settings to this json file
+    #[argh(option)]
+    bench_json: Option<String>,
+
+    /// render exactly this many frames at each benchmark position with a fixed 60fps time step,
+    /// so every run renders the same frames and only the timing differs
+    #[argh(option)]
+    bench_fixed_frames: Option<u32>,
+
+    /// frames rendered after each benchmark camera move before frames are counted (default: 5)
+    #[argh(option, default = "5")]
+    bench_warmup: u32,
+
+    /// benchmark once per value of a setting and print a table of the results, e.g.
+    /// shadow-cascades=1,2,3,4. Also shadow-distance, ssao-quality, resolution (1280x720) and
+    /// spot-shadows (off, on or a shadow map size)
+    #[argh(option, from_str_fn(parse_bench_sweep))]
+    bench_sweep: Option<SweepSpec>,
+
+    /// compare two --bench-json results given after the flags and exit without loading the
+    /// scene, e.g. --bench-compare old.json new.json. Fails if a frame time got slower by more
+    /// than --bench-threshold
+    #[argh(switch)]
+    bench_compare: bool,
+
+    /// percentage a frame time can get slower by before --bench-compare fails (default: 5)
+    #[argh(option, default = "5.0")]
+    bench_threshold: f32,
+
+    /// before the first benchmark, show every camera position for a few frames so all the
+    /// shaders are compiled before anything is measured
+    #[argh(switch)]
+    prewarm: bool,
+
+    /// show fps and entity counts on screen instead of logging them, toggle with F1
+    #[argh(switch)]
+    hud: bool,
+
+    /// also append the fps and frame time to this file once a second, with a unix timestamp
+    #[argh(option)]
+    diag_file: Option<String>,
+
+    /// toggle a wireframe on the scene meshes with F4
+    #[argh(switch)]
+    wireframe: bool,
+
+    /// give each material a flat color of its own instead of its textures, to see how the scene
+    /// is split into materials. Toggle it with slash
+    #[argh(switch)]
+    debug_material_colors: bool,
+
+    /// once loaded, fly through the camera positions over this many seconds and exit.
+    /// With --capture every frame is saved, advancing the flythrough at 60fps
+    #[argh(option)]
+    flythrough: Option<f32>,
+
+    /// time the main render passes on the gpu and add them to the benchmark results
+    #[argh(switch)]
+    gpu_timing: bool,
+
+    /// have the gpu frame debugger the app was started from, like RenderDoc, capture the nth
+    /// frame after the scene is processed and its mipmaps generated, then exit
+    #[argh(option)]
+    capture_frame: Option<u32>,
+
+    /// save a screenshot at each benchmark camera position
+    #[argh(switch)]
+    capture: bool,
+
+    /// with F10, render the view at this many times the resolution and save it scaled back down
+    #[argh(option)]
+    ss_capture: Option<u32>,
+
+    /// rotate the sun with [ and ]
+    #[argh(switch)]
+    sun_control: bool,
+
+    /// initial sun pitch in degrees (default: -77.4)
+    #[argh(option)]
+    sun_angle: Option<f32>,
+
+    /// sweep the sun from --sun-animate-from to --sun-animate-to over this many seconds, looping
+    #[argh(option)]
+    sun_animate: Option<f32>,
+
+    /// sun pitch in degrees the --sun-animate sweep starts at (default: -10.0, sunrise)
+    #[argh(option, default = "-10.0")]
+    sun_animate_from: f32,
+
+    /// sun pitch in degrees the --sun-animate sweep ends at (default: -170.0, sunset)
+    #[argh(option, default = "-170.0")]
+    sun_animate_to: f32,
+
+    /// speed of animations and camera tweens, 0 freezes them while TAA keeps accumulating,
+    /// halve and double it with Z and X, pause with P (default: 1.0)
+    #[argh(option, default = "1.0")]
+    time_scale: f32,
+
+    /// sun color as srgb hex or r,g,b, e.g. ffffff (default: a warm 0.95, 0.69, 0.54 linear)
+    #[argh(option, from_str_fn(parse_color))]
+    sun_color: Option<Color>,
+
+    /// sun illuminance in lux (default: 460000)
+    #[argh(option)]
+    sun_illuminance: Option<f32>,
+
+    /// add a dim bluish directional light shining from opposite the sun, without shadows
+    #[argh(switch)]
+    fill_light: bool,
+
+    /// illuminance of --fill-light in lux (default: 20000.0)
+    #[argh(option, default = "20000.0")]
+    fill_intensity: f32,
+
+    /// number of sun shadow cascades, 1 to 4 (default: 4)
+    #[argh(option, default = "4")]
+    shadow_cascades: u8,
+
+    /// distance the sun shadows reach from the camera (default: 30.0)
+    #[argh(option, default = "30.0")]
+    shadow_distance: f32,
+
+    /// press V to draw where each sun shadow cascade reaches from the current view
+    #[argh(switch)]
+    debug_cascades: bool,
+
+    /// sun shadow depth bias, raise it if there is shadow acne, adjust at runtime with Y and U (default: 0.04)
+    #[argh(option, default = "0.04")]
+    shadow_depth_bias: f32,
+
+    /// sun shadow normal bias, lower it if shadows detach from their casters, adjust at runtime with G and H (default: 1.8)
+    #[argh(option, default = "1.8")]
+    shadow_normal_bias: f32,
+
+    /// let the spot lights that fake the sun's bounce light cast shadows, measure what they
+    /// cost with --bench-sweep spot-shadows=off,on
+    #[argh(switch)]
+    spot_shadows: bool,
+
+    /// size of the --spot-shadows shadow maps. Bevy keeps them in one texture with the sun
+    /// cascades, so this sets the sun's shadow map size too (default: 2048)
+    #[argh(option)]
+    spot_shadow_map_size: Option<u32>,
+
+    /// anti-aliasing method: taa, fxaa or none (default: taa)
+    #[argh(option, default = "AntiAliasing::Taa")]
+    aa: AntiAliasing,
+
+    /// tonemapping method: tony, agx, aces, reinhard, reinhard-luminance, blender-filmic, boring
+    /// or none, cycle through them with T (default: tony)
+    #[argh(
+        option,
+        default = "Tonemapping::TonyMcMapface",
+        from_str_fn(parse_tonemapping)
+    )]
+    tonemap: Tonemapping,
+
+    /// ssao quality: low, medium, high or ultra, cycle through them with L (default: high)
+    #[argh(
+        option,
+        default = "ScreenSpaceAmbientOcclusionQualityLevel::High",
+        from_str_fn(parse_ssao_quality)
+    )]
+    ssao_quality: ScreenSpaceAmbientOcclusionQualityLevel,
+
+    /// diffuse transmission of alpha masked materials like foliage (default: 0.6)
+    #[argh(option)]
+    transmission: Option<f32>,
+
+    /// thickness of alpha masked materials like foliage (default: 0.2)
+    #[argh(option)]
+    thickness: Option<f32>,
+
+    /// leave alpha masked materials as flat cutouts without transmission
+    #[argh(switch)]
+    no_transmission: bool,
+
+    /// make every material opaque without transmission, to measure what the transmissive
+    /// and alpha masked materials cost (the scene will look wrong)
+    #[argh(switch)]
+    opaque_only: bool,
+
+    /// bloom intensity, adjust at runtime with ; and ' (default: 0.05)
+    #[argh(option, default = "0.05")]
+    bloom_intensity: f32,
+
+    /// brightness below which pixels don't bloom (default: 0.0)
+    #[argh(option, default = "0.0")]
+    bloom_threshold: f32,
+
+    /// boost of the wide, low frequency part of the bloom (default: 0.7)
+    #[argh(option)]
+    bloom_low_frequency_boost: Option<f32>,
+
+    /// don't load the environment map, light the shadows with a flat ambient light instead
+    #[argh(switch)]
+    no_env_map: bool,
+
+    /// ambient light color with --no-env-map, as srgb hex or r,g,b (default: 0.85,0.9,1.0)
+    #[argh(
+        option,
+        default = "Color::rgb(0.85, 0.9, 1.0)",
+        from_str_fn(parse_color)
+    )]
+    ambient_color: Color,
+
+    /// background color as srgb hex or r,g,b, components above 1.0 are hdr and bloom
+    /// (default: 1.75,1.8,2.1, a bright sky)
+    #[argh(
+        option,
+        default = "Color::rgb(1.75, 1.8, 2.1)",
+        from_str_fn(parse_color)
+    )]
+    clear_color: Color,
+
+    /// diffuse environment map ktx2, relative to the assets directory (default: the pisa map)
+    #[argh(option, default = "String::from(DEFAULT_ENV_DIFFUSE)")]
+    env_diffuse: String,
+
+    /// specular environment map ktx2, relative to the assets directory (default: the pisa map)
+    #[argh(option, default = "String::from(DEFAULT_ENV_SPECULAR)")]
+    env_specular: String,
+
+    /// environment map intensity (default: 1000.0)
+    #[argh(option, default = "1000.0")]
+    env_intensity: f32,
+
+    /// ron file that rebinds the keys of actions like benchmark, next_cam_pos and
+    /// print_transform, e.g. (benchmark: Numpad0, cam_positions: [Numpad1, Numpad2]).
+    /// Two actions can't share a key
+    #[argh(option)]
+    keymap: Option<String>,
+
+    /// ron file mapping gltf material names to the base_color, metallic, roughness and emissive
+    /// to give them, e.g. {"Gold": (metallic: 1.0, roughness: 0.3)}. R reads it again
+    #[argh(option)]
+    material_overrides: Option<String>,
+
+    /// ron file the camera, exposure and post effects are restored from and saved to on exit
+    #[argh(option)]
+    session: Option<String>,
+
+    /// camera exposure in stops, adjust at runtime with - and = (default: -2.0)
+    #[argh(option, default = "-2.0")]
+    exposure: f32,
+
+    /// adjust the exposure every frame towards the one that brings the image's average
+    /// luminance to middle gray, like eyes adapting. Changing it with - or = turns this off
+    #[argh(switch)]
+    auto_exposure: bool,
+
+    /// how quickly --auto-exposure adapts, the share of the gap closed per second is about
+    /// this for small values (default: 1.0)
+    #[argh(option, default = "1.0")]
+    auto_exposure_speed: f32,
+
+    /// lowest exposure --auto-exposure goes to, in stops (default: -6.0)
+    #[argh(option, default = "-6.0")]
+    auto_exposure_min: f32,
+
+    /// highest exposure --auto-exposure goes to, in stops (default: 2.0)
+    #[argh(option, default = "2.0")]
+    auto_exposure_max: f32,
+
+    /// write the scene's materials to this json file once they are processed, then exit
+    #[argh(option)]
+    dump_materials: Option<String>,
+
+    /// write a copy of the scene's .gltf with the processed materials baked in once they are
+    /// processed, then exit. Buffers and textures are referenced from the original location
+    #[argh(option)]
+    export_gltf: Option<String>,
+
+    /// check the scene's materials for NaN and out of range values once they are processed,
+    /// then exit, with an error if any were found
+    #[argh(switch)]
+    check_materials: bool,
+
+    /// halve textures larger than this many pixels before generating their mips, for faster loads
+    #[argh(option)]
+    max_texture_size: Option<u32>,
+
+    /// skip generating mipmaps for faster startup, distant textures will alias
+    #[argh(switch)]
+    no_mipmaps: bool,
+
+    /// merge duplicate vertices and reorder each mesh for the gpu's vertex cache as it loads,
+    /// costs some load time
+    #[argh(switch)]
+    optimize_meshes: bool,
+
+    /// generate two simplified versions of each mesh as it loads and draw them as the mesh gets
+    /// further from the camera
+    #[argh(switch)]
+    lod: bool,
+
+    /// anisotropic filtering level of the material textures, 1, 2, 4, 8 or 16 (default: 16)
+    #[argh(option, default = "16")]
+    aniso: u16,
+
+    /// once loaded, drop the top mips of the largest textures until they fit in this many MiB.
+    /// Textures are only uploaded to the gpu after that, at their final size
+    #[argh(option)]
+    texture_budget_mb: Option<u32>,
+
+    /// cap the frame rate, ignored while benchmarking
+    #[argh(option)]
+    max_fps: Option<u32>,
+
+    /// window present mode: immediate, fifo, mailbox, auto-vsync or auto-no-vsync (default: immediate)
+    #[argh(
+        option,
+        default = "PresentMode::Immediate",
+        from_str_fn(parse_present_mode)
+    )]
+    present_mode: PresentMode,
+
+    /// the baseline and new --bench-json files for --bench-compare
+    #[argh(positional)]
+    bench_files: Vec<String>,
+}
+
+/// srgb hex, or r,g,b srgb components which can go above 1.0 for hdr colors
+fn parse_color(value: &str) -> Result<Color, String> {
+    if value.contains(',') {
+        let rgb = parse_vec3(value)?;
+        if rgb.min_element() < 0.0 {
+            return Err(format!(
+                "invalid color {value}, components can't be negative"
+            ));
+        }
+        return Ok(Color::rgb(rgb.x, rgb.y, rgb.z));
+    }
+    Color::hex(value).map_err(|e| format!("invalid color {value}: {e}"))
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid vector {value}: {e}"))?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("invalid vector {value}, expected x,y,z")),
+    }
+}
+
+/// Tonemapping methods by their --tonemap name, in the order T cycles through them
+const TONEMAPPING_METHODS: [(&str, Tonemapping); 8] = [
+    ("tony", Tonemapping::TonyMcMapface),
+    ("agx", Tonemapping::AgX),
+    ("aces", Tonemapping::AcesFitted),
+    ("reinhard", Tonemapping::Reinhard),
+    ("reinhard-luminance", Tonemapping::ReinhardLuminance),
+    ("blender-filmic", Tonemapping::BlenderFilmic),
+    ("boring", Tonemapping::SomewhatBoringDisplayTransform),
+    ("none", Tonemapping::None),
+];
+
+fn parse_tonemapping(value: &str) -> Result<Tonemapping, String> {
+    let value = value.to_lowercase();
+    TONEMAPPING_METHODS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, tonemapping)| *tonemapping)
+        .ok_or_else(|| {
+            let names = TONEMAPPING_METHODS.map(|(name, _)| name).join(", ");
+            format!("unknown tonemapping method {value}, expected one of {names}")
+        })
+}
+
+pub const SSAO_QUALITY_LEVELS: [(&str, ScreenSpaceAmbientOcclusionQualityLevel); 4] = [
+    ("low", ScreenSpaceAmbientOcclusionQualityLevel::Low),
+    ("medium", ScreenSpaceAmbientOcclusionQualityLevel::Medium),
+    ("high", ScreenSpaceAmbientOcclusionQualityLevel::High),
+    ("ultra", ScreenSpaceAmbientOcclusionQualityLevel::Ultra),
+];
+
+fn parse_ssao_quality(value: &str) -> Result<ScreenSpaceAmbientOcclusionQualityLevel, String> {
+    let value = value.to_lowercase();
+    SSAO_QUALITY_LEVELS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, quality)| *quality)
+        .ok_or_else(|| {
+            let names = SSAO_QUALITY_LEVELS.map(|(name, _)| name).join(", ");
+            format!("unknown ssao quality {value}, expected one of {names}")
+        })
+}
+
+fn parse_present_mode(value: &str) -> Result<PresentMode, String> {
+    match value.to_lowercase().as_str() {
+        "immediate" => Ok(PresentMode::Immediate),
+        "fifo" => Ok(PresentMode::Fifo),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "auto-vsync" => Ok(PresentMode::AutoVsync),
+        "auto-no-vsync" => Ok(PresentMode::AutoNoVsync),
+        _ => Err(format!(
+            "unknown present mode {value}, expected one of immediate, fifo, mailbox, auto-vsync, auto-no-vsync"
+        )),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    Taa,
+    Fxaa,
+    None,
+}
+
+impl FromStr for AntiAliasing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "taa" => Ok(AntiAliasing::Taa),
+            "fxaa" => Ok(AntiAliasing::Fxaa),
+            "none" => Ok(AntiAliasing::None),
+            _ => Err(format!(
+                "unknown anti-aliasing method {s}, expected one of taa, fxaa, none"
+            )),
+        }
+    }
+}
+
+impl Args {
+    pub fn resolution(&self) -> (u32, u32) {
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            (width, height)
+        } else if self.p720 {
+            (1280, 720)
+        } else {
+            (1920, 1080)
+        }
+    }
 }
 
 pub fn main() {
-    let args: Args = argh::from_env();
+    let args = config::args_from_env();
+    if let Some(path) = &args.config {
+        println!("Using settings from {path}, command line flags override them");
+    }
+
+    for (name, value) in [
+        ("--cam-speed", args.cam_speed),
+        ("--cam-sensitivity", args.cam_sensitivity),
+        ("--cam-smooth", args.cam_smooth),
+        ("--cam-max-roll", args.cam_max_roll),
+        ("--flythrough", args.flythrough),
+        ("--sun-animate", args.sun_animate),
+        ("--watchdog", args.watchdog),
+        ("--clamp-luminance", args.clamp_luminance),
+        ("--auto-exposure-speed", Some(args.auto_exposure_speed)),
+    ] {
+        if let Some(value) = value {
+            if !(value > 0.0 && value.is_finite()) {
+                eprintln!("{name} must be a positive number, got {value}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for (name, value) in [
+        ("--bloom-intensity", Some(args.bloom_intensity)),
+        ("--bloom-threshold", Some(args.bloom_threshold)),
+        (
+            "--bloom-low-frequency-boost",
+            args.bloom_low_frequency_boost,
+        ),
+        ("--sun-illuminance", args.sun_illuminance),
+        ("--fill-intensity", Some(args.fill_intensity)),
+        ("--shadow-depth-bias", Some(args.shadow_depth_bias)),
+        ("--shadow-normal-bias", Some(args.shadow_normal_bias)),
+        ("--env-intensity", Some(args.env_intensity)),
+        ("--time-scale", Some(args.time_scale)),
+        ("--bench-threshold", Some(args.bench_threshold)),
+    ] {
+        if let Some(value) = value {
+            if !(value >= 0.0 && value.is_finite()) {
+                eprintln!("{name} must be a number >= 0, got {value}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.review_lighting && (args.keep_scene_lights || args.fill_light) {
+        eprintln!("--review-lighting replaces the lights, it can't be used with --keep-scene-lights or --fill-light");
+        std::process::exit(1);
+    }
+
+    if args.clamp_luminance.is_some() && args.no_hdr {
+        eprintln!("--clamp-luminance only works on the hdr image, it can't be used with --no-hdr");
+        std::process::exit(1);
+    }
+
+    if args.auto_exposure && args.no_hdr {
+        eprintln!("--auto-exposure measures the hdr image, it can't be used with --no-hdr");
+        std::process::exit(1);
+    }
+    let exposure_range = MIN_EXPOSURE..=MAX_EXPOSURE;
+    if !(exposure_range.contains(&args.auto_exposure_min)
+        && exposure_range.contains(&args.auto_exposure_max)
+        && args.auto_exposure_min <= args.auto_exposure_max)
+    {
+        eprintln!(
+            "--auto-exposure-min and --auto-exposure-max must be between {MIN_EXPOSURE} and {MAX_EXPOSURE}, min first"
+        );
+        std::process::exit(1);
+    }
+
+    if args.capture_frame == Some(0) {
+        eprintln!("--capture-frame counts from 1, the first frame after the scene is loaded");
+        std::process::exit(1);
+    }
+
+    if args.spot_shadow_map_size.is_some() && !args.spot_shadows {
+        eprintln!("--spot-shadow-map-size requires --spot-shadows");
+        std::process::exit(1);
+    }
+    if args
+        .spot_shadow_map_size
+        .is_some_and(|size| !(1..=MAX_SHADOW_MAP_SIZE).contains(&size))
+    {
+        eprintln!("--spot-shadow-map-size must be between 1 and {MAX_SHADOW_MAP_SIZE}");
+        std::process::exit(1);
+    }
+
+    if args.watchdog_exit && args.watchdog.is_none() {
+        eprintln!("--watchdog-exit requires --watchdog");
+        std::process::exit(1);
+    }
+
+    if args.transmission.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+        eprintln!("--transmission must be between 0 and 1");
+        std::process::exit(1);
+    }
+    if args.thickness.is_some_and(|t| !(t >= 0.0 && t.is_finite())) {
+        eprintln!("--thickness must be a number >= 0");
+        std::process::exit(1);
+    }
+    let mut transmission_settings = TransmissionSettings {
+        enabled: !args.no_transmission && !args.opaque_only,
+        ..default()
+    };
+    if let Some(transmission) = args.transmission {
+        transmission_settings.diffuse_transmission = transmission;
+    }
+    if let Some(thickness) = args.thickness {
+        transmission_settings.thickness = thickness;
+    }
+
+    if !args.exposure.is_finite() {
+        eprintln!("--exposure must be a number, got {}", args.exposure);
+        std::process::exit(1);
+    }
+
+    match (args.width, args.height) {
+        (Some(_), None) | (None, Some(_)) => {
+            eprintln!("--width and --height have to be used together");
+            std::process::exit(1);
+        }
+        (Some(0), _) | (_, Some(0)) => {
+            eprintln!("--width and --height must be greater than 0");
+            std::process::exit(1);
+        }
+        _ => (),
+    }
+
+    if !(1..=4).contains(&args.shadow_cascades) {
+        eprintln!("--shadow-cascades must be between 1 and 4");
+        std::process::exit(1);
+    }
+    if !(args.fov > 0.0 && args.fov < 180.0) {
+        eprintln!("--fov must be between 0 and 180 degrees");
+        std::process::exit(1);
+    }
+
+    if !(args.shadow_distance > 0.0 && args.shadow_distance.is_finite()) {
+        eprintln!("--shadow-distance must be a positive number");
+        std::process::exit(1);
+    }
+
+    if args
+        .ss_capture
+        .is_some_and(|factor| !(2..=8).contains(&factor))
+    {
+        eprintln!("--ss-capture must be between 2 and 8");
+        std::process::exit(1);
+    }
+
+    if args.scene_instances == 0 {
+        eprintln!("--scene-instances must be at least 1");
+        std::process::exit(1);
+    }
+
+    if args.max_texture_size == Some(0) {
+        eprintln!("--max-texture-size must be at least 1");
+        std::process::exit(1);
+    }
+
+    if args.no_mipmaps && args.convert {
+        eprintln!("--no-mipmaps can't be used with --convert, converted textures always have mips");
+        std::process::exit(1);
+    }
+
+    let scene_extension = Path::new(scene_file(&args.scene)).extension();
+    if !scene_extension.is_some_and(|ext| ext == "gltf" || ext == "glb") {
+        eprintln!("--scene must be a .gltf or .glb file, got {}", args.scene);
+        std::process::exit(1);
+    }
+
+    if args.export_gltf.is_some() && scene_extension.is_some_and(|ext| ext == "glb") {
+        eprintln!("--export-gltf needs a .gltf --scene, .glb files aren't supported");
+        std::process::exit(1);
+    }
+
+    if ![1, 2, 4, 8, 16].contains(&args.aniso) {
+        eprintln!("--aniso must be 1, 2, 4, 8 or 16");
+        std::process::exit(1);
+    }
+
+    if args.texture_budget_mb == Some(0) {
+        eprintln!("--texture-budget-mb must be at least 1");
+        std::process::exit(1);
+    }
+
+    if args.max_fps == Some(0) {
+        eprintln!("--max-fps must be at least 1");
+        std::process::exit(1);
+    }
+
+    if args.bench_fixed_frames == Some(0) {
+        eprintln!("--bench-fixed-frames must be at least 1");
+        std::process::exit(1);
+    }
 
+    if args.zstd_level.is_some() && args.no_zstd {
+        eprintln!("--zstd-level can't be combined with --no-zstd");
+        std::process::exit(1);
+    }
+
+    if args.convert_threads == Some(0) {
+        eprintln!("--convert-threads must be at least 1");
+        std::process::exit(1);
+    }
+
+    if args.single_thread && args.convert_threads.is_some_and(|threads| threads > 1) {
+        eprintln!("--single-thread can't be used with more than one --convert-threads");
+        std::process::exit(1);
+    }
+
+    if !args.bench_compare && !args.bench_files.is_empty() {
+        eprintln!(
+            "Unexpected arguments {}, only --bench-compare takes files",
+            args.bench_files.join(" ")
+        );
+        std::process::exit(1);
+    }
+    if args.bench_compare {
+        let [baseline, new] = &args.bench_files[..] else {
+            eprintln!("--bench-compare needs the two json files to compare, e.g. --bench-compare old.json new.json");
+            std::process::exit(1);
+        };
+        match compare_bench_json(Path::new(baseline), Path::new(new), args.bench_threshold) {
+            Ok(false) => return,
+            Ok(true) => {
+                eprintln!(
+                    "Frame times regressed by more than {}%",
+                    args.bench_threshold
+                );
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to compare the benchmarks: {e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let src = Path::new(args.convert_src.as_deref().unwrap_or(DEFAULT_CONVERT_DIR));
+    let dst = args.convert_dst.as_deref().map_or(src, Path::new);
     if args.convert {
-        println!("This will take a few minutes");
-        convert_images_to_ktx2();
-        change_gltf_to_use_ktx2();
+        if !args.convert_dry_run {
+            println!("This will take a few minutes");
+        }
+        if let Err(e) = find_gltf_files(src)
+            .and_then(|_| {
+                convert_images_to_ktx2(
+                    src,
+                    dst,
+                    args.convert_threads.or(args.single_thread.then_some(1)),
+                    args.compression_format,
+                    args.compression_speed,
+                    (!args.no_zstd).then_some(args.zstd_level.unwrap_or(0)),
+                    args.convert_dry_run,
+                )
+            })
+            .and_then(|_| change_gltf_to_use_ktx2(src, dst, args.convert_dry_run))
+        {
+            eprintln!("Conversion failed: {e:#}");
+            std::process::exit(1);
+        }
+        if args.convert_dry_run {
+            return;
+        }
+    }
+
+    if args.validate {
+        if let Err(e) = validate_ktx2_images(src, dst) {
+            eprintln!("Validation failed: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let camera_positions = match &args.cam_positions {
+        Some(path) => CameraPositions::load(path).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }),
+        None => CameraPositions::default(),
+    };
+    if camera_positions.is_empty() {
+        eprintln!("--cam-positions has to contain at least one position");
+        std::process::exit(1);
+    }
+    if args.flythrough.is_some() && camera_positions.len() < 2 {
+        eprintln!("--flythrough needs at least two camera positions");
+        std::process::exit(1);
     }
 
+    let session = args
+        .session
+        .as_ref()
+        .filter(|path| Path::new(path).exists())
+        .map(|path| {
+            Session::load(path).unwrap_or_else(|e| {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            })
+        });
+
+    let material_overrides = args.material_overrides.as_ref().map(|path| {
+        MaterialOverrides::load(path, &args.scene).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        })
+    });
+
+    let key_bindings = args
+        .keymap
+        .as_ref()
+        .map_or_else(KeyBindings::default, |path| {
+            KeyBindings::load(path).unwrap_or_else(|e| {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            })
+        });
+
+    let (width, height) = args.resolution();
+
     let mut app = App::new();
+    if let Some(material_overrides) = material_overrides {
+        app.insert_resource(material_overrides);
+    }
 
     app.insert_resource(args.clone())
+        .insert_resource(camera_positions)
+        .insert_resource(key_bindings)
+        .init_non_send_resource::<Clipboard>()
+        .insert_resource(transmission_settings)
+        .init_resource::<RecordedCameraPositions>()
+        .init_resource::<BounceLightScale>()
+        .init_resource::<BenchmarkRunning>()
         .insert_resource(Msaa::Off)
-        .insert_resource(ClearColor(Color::rgb(1.75, 1.8, 2.1)))
-        .insert_resource(AmbientLight {
-            color: Color::rgb(0.0, 0.0, 0.0),
-            brightness: 0.0,
+        .insert_resource(ClearColor(args.clear_color))
+        .insert_resource(if args.review_lighting {
+            AmbientLight {
+                color: Color::WHITE,
+                brightness: REVIEW_AMBIENT_BRIGHTNESS,
+            }
+        } else if args.no_env_map {
+            // Without the environment map everything in shadow would be black
+            AmbientLight {
+                color: args.ambient_color,
+                brightness: 400.0,
+            }
+        } else {
+            AmbientLight {
+                color: Color::rgb(0.0, 0.0, 0.0),
+                brightness: 0.0,
+            }
         })
         .insert_resource(WinitSettings {
             focused_mode: UpdateMode::Continuous,
             unfocused_mode: UpdateMode::Continuous,
-        })
-        .add_plugins(
-            DefaultPlugins.set(WindowPlugin {
+        });
+
+    let mut default_plugins = DefaultPlugins.build();
+    if args.single_thread {
+        default_plugins = default_plugins
+            .set(TaskPoolPlugin {
+                task_pool_options: TaskPoolOptions::with_num_threads(1),
+            })
+            // Otherwise the render world is extracted and drawn on its own thread
+            .disable::<PipelinedRenderingPlugin>();
+    }
+    if args.bench_headless {
+        // No window, the camera renders into an image instead (see setup)
+        app.add_plugins((
+            default_plugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+        ));
+    } else {
+        app.add_plugins(
+            default_plugins.set(WindowPlugin {
                 primary_window: Some(Window {
-                    present_mode: PresentMode::Immediate,
-                    resolution: if args.p720 {
-                        WindowResolution::new(1280.0, 720.0)
-                    } else {
-                        WindowResolution::new(1920.0, 1080.0)
-                    }
-                    .with_scale_factor_override(1.0),
+                    present_mode: args.present_mode,
+                    resolution: WindowResolution::new(width as f32, height as f32)
+                        .with_scale_factor_override(1.0),
                     ..default()
                 }),
                 ..default()
             }),
-        )
-        .add_plugins(LogDiagnosticsPlugin::default())
-        .add_plugins(FrameTimeDiagnosticsPlugin)
+        );
+    }
+
+    // The hud shows the same numbers without flooding the terminal
+    if args.hud && !args.bench_headless {
+        app.add_plugins(HudPlugin);
+    } else {
+        app.add_plugins(LogDiagnosticsPlugin::default());
+    }
+    if let Some(path) = &args.diag_file {
+        match DiagFile::open(Path::new(path)) {
+            Ok(diag_file) => {
+                app.insert_resource(diag_file)
+                    .add_systems(Update, write_diag_file);
+            }
+            Err(e) => {
+                eprintln!("Failed to open the diagnostics file {path}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    app.add_plugins(FrameTimeDiagnosticsPlugin)
         // Generating mipmaps takes a minute
         .insert_resource(MipmapGeneratorSettings {
-            anisotropic_filtering: 16,
+            anisotropic_filtering: args.aniso,
+            max_texture_size: args.max_texture_size,
             ..default()
         })
         .add_plugins((
             MipmapGeneratorPlugin,
             CameraControllerPlugin,
-            TemporalAntiAliasPlugin,
+            CapturePlugin,
+            FrustumFreezePlugin,
+            GridPlugin,
         ))
-        // Mipmap generation be skipped if ktx2 is used
+        .insert_resource(GridVisible(args.grid))
         .add_systems(
             Update,
             (
-                generate_mipmaps::<StandardMaterial>,
                 consolidate_material_instances::<StandardMaterial>,
                 proc_scene,
                 input,
+                toggle_post_effects,
+                material_stats,
+                draw_call_stats,
+                tween_camera.run_if(time_running),
                 benchmark,
+                scale_bounce_lights,
+                adjust_bloom,
+                adjust_shadow_bias,
+                toggle_projection,
+                cycle_msaa,
+                reload_scene,
+                adjust_time_scale,
+                cycle_tonemapping,
+                cycle_ssao_quality,
             ),
         )
-        .add_systems(Startup, setup);
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                check_watched_assets.run_if(resource_exists::<WatchedAssets>),
+                compute_scene_bounds.run_if(not(resource_exists::<SceneBounds>)),
+                report_load_timing,
+            ),
+        );
 
+    if args.no_mipmaps {
+        // Nothing to wait for before the scene counts as loaded
+        app.init_resource::<MipmapTasks<StandardMaterial>>();
+    } else {
+        // Mipmap generation be skipped if ktx2 is used
+        app.add_systems(Update, generate_mipmaps::<StandardMaterial>);
+    }
+    if args.aa == AntiAliasing::Taa {
+        app.add_plugins(TemporalAntiAliasPlugin);
+    }
+    if args.flythrough.is_some() {
+        app.add_systems(Update, flythrough);
+    }
+    if args.dump_materials.is_some() {
+        app.add_systems(Update, dump_materials);
+    }
+    if args.check_materials {
+        app.add_systems(Update, check_materials);
+    }
+    if args.export_gltf.is_some() {
+        app.add_systems(Update, export_gltf);
+    }
+    if args.optimize_meshes {
+        app.add_systems(Update, optimize_meshes);
+    }
+    if args.lod {
+        app.add_plugins(MeshLodPlugin);
+    }
+    if args.clamp_luminance.is_some() {
+        app.add_plugins(LuminanceClampPlugin);
+    }
+    if args.auto_exposure {
+        app.add_plugins(AutoExposurePlugin);
+    }
+    if let Some(spec) = &args.bench_sweep {
+        app.insert_resource(BenchSweep::new(spec.clone()))
+            .add_systems(Update, apply_bench_sweep.before(benchmark));
+    }
+    if let Some(timeout) = args.watchdog {
+        app.insert_resource(Watchdog::start(
+            Duration::from_secs_f32(timeout),
+            args.watchdog_exit,
+        ))
+        .add_systems(Last, watchdog_heartbeat);
+    }
+    if args.cam_positions.is_some() {
+        app.add_systems(Update, watch_camera_positions);
+    }
+    if args.minimap && !args.bench_headless {
+        app.add_plugins(MinimapPlugin);
+    }
+    if args.debug_cascades {
+        app.add_systems(Update, debug_cascades);
+    }
+    if args.texture_budget_mb.is_some() {
+        app.init_resource::<HeldTextures>()
+            .add_systems(
+                Update,
+                apply_texture_budget.after(generate_mipmaps::<StandardMaterial>),
+            )
+            .add_systems(Last, hold_textures);
+    }
+    if args.max_fps.is_some() && !args.bench_headless {
+        app.add_systems(Last, limit_frame_rate);
+    }
+    if args.wireframe {
+        app.add_plugins(WireframePlugin)
+            .add_systems(Update, toggle_wireframe);
+    }
+    if args.debug_material_colors {
+        app.init_resource::<DebugMaterialColors>()
+            .add_systems(Update, toggle_debug_material_colors);
+    }
+    if args.gpu_timing {
+        app.add_plugins(GpuTimingPlugin);
+    }
+    if let Some(size) = args.spot_shadow_map_size {
+        app.insert_resource(DirectionalLightShadowMap {
+            size: size as usize,
+        });
+    }
+    if let Some(frame) = args.capture_frame {
+        app.add_plugins(FrameCapturePlugin { frame });
+    }
+    if let Some(session) = session {
+        app.insert_resource(session);
+    }
+    if args.session.is_some() {
+        app.add_systems(Last, save_session_on_exit);
+    }
     if args.no_frustum_culling {
         app.add_systems(Update, add_no_frustum_culling);
     }
+    if args.sun_control {
+        app.add_systems(Update, sun_control);
+    }
+    if args.sun_animate.is_some() {
+        app.add_systems(Update, sun_animate.run_if(time_running));
+    }
     if args.instance {
         app.add_plugins((
             AutoInstancePlugin,
@@ -128,76 +1296,167 @@ pub fn main() {
         ));
     }
 
+    if args.single_thread {
+        single_threaded_schedules(&mut app);
+    }
+
     app.run();
 }
 
+/// Even with one thread in the task pools, the multi-threaded executor also runs systems on the
+/// main thread. Switch every schedule of the main and render worlds to the single-threaded one.
+fn single_threaded_schedules(app: &mut App) {
+    for (_, schedule) in app.world.resource_mut::<Schedules>().iter_mut() {
+        schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    }
+    let render_app = app.sub_app_mut(RenderApp);
+    for (_, schedule) in render_app.world.resource_mut::<Schedules>().iter_mut() {
+        schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    }
+}
+
 #[derive(Component)]
 pub struct PostProcScene;
 
+/// Applied by [`proc_scene`] to alpha masked materials (mostly foliage and curtains)
+#[derive(Resource, Clone, Copy)]
+pub struct TransmissionSettings {
+    /// If false masked materials are left untouched
+    pub enabled: bool,
+    pub diffuse_transmission: f32,
+    pub thickness: f32,
+}
+
+impl Default for TransmissionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            diffuse_transmission: 0.6,
+            thickness: 0.2,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct GrifLight;
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<Args>) {
-    println!("Loading models, generating mipmaps");
+#[derive(Component)]
+pub struct SunLight;
 
-    // San Miguel
+/// Dim directional light from the opposite side of the sun, with --fill-light
+#[derive(Component)]
+pub struct FillLight;
+
+fn sun_rotation(args: &Args) -> Quat {
+    Quat::from_euler(
+        EulerRot::XYZ,
+        args.sun_angle.map_or(PI * -0.43, f32::to_radians),
+        SUN_YAW,
+        0.0,
+    )
+}
+
+/// Authored intensity of a bounce light, scaled by [`BounceLightScale`]
+#[derive(Component)]
+pub struct BaseIntensity(pub f32);
+
+/// Multiplier for the intensity of all spot and point [`GrifLight`]s, adjust with , and .
+#[derive(Resource)]
+pub struct BounceLightScale(pub f32);
+
+impl Default for BounceLightScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+fn bounce_light(transform: Transform, spot_light: SpotLight) -> impl Bundle {
+    (
+        BaseIntensity(spot_light.intensity),
+        SpotLightBundle {
+            transform,
+            spot_light,
+            ..default()
+        },
+        GrifLight,
+    )
+}
+
+// The largest texture size wgpu guarantees
+pub const MAX_SHADOW_MAP_SIZE: u32 = 8192;
+
+/// Shadow cascades of the sun, reaching `distance` from the camera
+fn sun_cascades(cascades: u8, distance: f32) -> CascadeShadowConfig {
+    CascadeShadowConfigBuilder {
+        num_cascades: cascades as usize,
+        maximum_distance: distance,
+        // Keep the first cascade inside short distances
+        first_cascade_far_bound: distance.min(5.0),
+        ..default()
+    }
+    .into()
+}
+
+// --review-lighting: a white albedo lit by the light and the ambient ends up close to 1.0
+// at the neutral exposure
+const REVIEW_ILLUMINANCE: f32 = 2000.0;
+const REVIEW_AMBIENT_BRIGHTNESS: f32 = 300.0;
+const REVIEW_EXPOSURE: f32 = 0.0;
+
+/// The single white light of --review-lighting, from high up so every surface facing up or
+/// sideways gets some of it
+fn spawn_review_light(commands: &mut Commands) {
     commands.spawn((
-        SceneBundle {
-            scene: asset_server.load("san-miguel/san-miguel.gltf#Scene0"),
-            transform: Transform::from_xyz(-18.0, 0.0, 0.0),
+        DirectionalLightBundle {
+            transform: Transform::from_xyz(1.0, 3.0, 2.0).looking_at(Vec3::ZERO, Vec3::Y),
+            directional_light: DirectionalLight {
+                color: Color::WHITE,
+                illuminance: REVIEW_ILLUMINANCE,
+                shadows_enabled: false,
+                ..default()
+            },
             ..default()
         },
-        PostProcScene,
-        AutoInstanceMaterialRecursive,
-        AutoInstanceMeshRecursive,
+        GrifLight,
     ));
+}
 
+/// Spawns the sun and the spot lights that fake its bounce light in the courtyard
+fn spawn_light_rig(commands: &mut Commands, args: &Args) {
     // Sun
     commands.spawn((
         DirectionalLightBundle {
-            transform: Transform::from_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                PI * -0.43,
-                PI * -0.08,
-                0.0,
-            )),
+            transform: Transform::from_rotation(sun_rotation(args)),
             directional_light: DirectionalLight {
-                color: Color::rgb_linear(0.95, 0.69268, 0.537758),
-                illuminance: 2300000.0 * 0.2,
+                color: args
+                    .sun_color
+                    .unwrap_or(Color::rgb_linear(0.95, 0.69268, 0.537758)),
+                illuminance: args.sun_illuminance.unwrap_or(2300000.0 * 0.2),
                 shadows_enabled: !args.minimal,
-                shadow_depth_bias: 0.04,
-                shadow_normal_bias: 1.8,
+                shadow_depth_bias: args.shadow_depth_bias,
+                shadow_normal_bias: args.shadow_normal_bias,
             },
-            cascade_shadow_config: CascadeShadowConfigBuilder {
-                num_cascades: 4,
-                maximum_distance: 30.0,
-                ..default()
-            }
-            .into(),
+            cascade_shadow_config: sun_cascades(args.shadow_cascades, args.shadow_distance),
             ..default()
         },
         GrifLight,
+        SunLight,
     ));
 
     let point_spot_mult = 1000.0;
     // Sun Wall Refl
-    commands.spawn((
-        SpotLightBundle {
-            transform: Transform::from_xyz(4.5, 4.0, 4.5)
-                .looking_at(Vec3::new(-999.0, 0.0, 0.0), Vec3::Y),
-            spot_light: SpotLight {
-                range: 15.0,
-                radius: 1.5,
-                intensity: 250.0 * point_spot_mult,
-                color: Color::rgb(1.75, 1.9, 1.9),
-                shadows_enabled: false,
-                inner_angle: PI * 0.4,
-                outer_angle: PI * 0.5,
-                ..default()
-            },
+    commands.spawn(bounce_light(
+        Transform::from_xyz(4.5, 4.0, 4.5).looking_at(Vec3::new(-999.0, 0.0, 0.0), Vec3::Y),
+        SpotLight {
+            range: 15.0,
+            radius: 1.5,
+            intensity: 250.0 * point_spot_mult,
+            color: Color::rgb(1.75, 1.9, 1.9),
+            shadows_enabled: args.spot_shadows,
+            inner_angle: PI * 0.4,
+            outer_angle: PI * 0.5,
             ..default()
         },
-        GrifLight,
     ));
 
     // Sun Ground Refl
@@ -206,22 +1465,18 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
         Transform::from_xyz(-1.5, 0.5, 1.5),
         Transform::from_xyz(-5.0, 0.5, 1.5),
     ] {
-        commands.spawn((
-            SpotLightBundle {
-                transform: t.looking_at(Vec3::new(0.0, 999.0, 0.0), Vec3::X),
-                spot_light: SpotLight {
-                    range: 15.0,
-                    radius: 4.0,
-                    intensity: 1000.0 * point_spot_mult,
-                    color: Color::rgb(1.0, 0.85, 0.75),
-                    shadows_enabled: false,
-                    inner_angle: PI * 0.4,
-                    outer_angle: PI * 0.5,
-                    ..default()
-                },
+        commands.spawn(bounce_light(
+            t.looking_at(Vec3::new(0.0, 999.0, 0.0), Vec3::X),
+            SpotLight {
+                range: 15.0,
+                radius: 4.0,
+                intensity: 1000.0 * point_spot_mult,
+                color: Color::rgb(1.0, 0.85, 0.75),
+                shadows_enabled: args.spot_shadows,
+                inner_angle: PI * 0.4,
+                outer_angle: PI * 0.5,
                 ..default()
             },
-            GrifLight,
         ));
     }
 
@@ -230,70 +1485,261 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
         Transform::from_xyz(2.95, 0.5, 3.15),
         Transform::from_xyz(-6.2, 0.5, 2.3),
     ] {
+        commands.spawn(bounce_light(
+            t.looking_at(Vec3::new(0.0, 999.0, 0.0), Vec3::X),
+            SpotLight {
+                range: 3.0,
+                radius: 1.5,
+                intensity: 150.0 * point_spot_mult,
+                color: Color::rgb(1.0, 0.95, 0.9),
+                shadows_enabled: args.spot_shadows,
+                inner_angle: PI * 0.4,
+                outer_angle: PI * 0.5,
+                ..default()
+            },
+        ));
+    }
+}
+
+// Distance between the copies of the scene with --scene-instances, San Miguel is about 40m across
+const SCENE_INSTANCE_SPACING: f32 = 50.0;
+
+/// Spawns --scene to be processed by [`proc_scene`], also used to reload it.
+/// With --scene-instances the copies are laid out in a square grid and share one scene handle,
+/// so their meshes and materials are only loaded once.
+pub fn spawn_scene(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    args: &Args,
+) -> Handle<Scene> {
+    let scene = asset_server.load(scene_asset_path(&args.scene));
+    let columns = (args.scene_instances as f32).sqrt().ceil() as u32;
+    for i in 0..args.scene_instances {
+        let offset = Vec3::new((i % columns) as f32, 0.0, (i / columns) as f32);
+        commands.spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                transform: Transform::from_translation(
+                    Vec3::new(-18.0, 0.0, 0.0) + offset * SCENE_INSTANCE_SPACING,
+                ),
+                ..default()
+            },
+            PostProcScene,
+            SceneRoot,
+            AutoInstanceMaterialRecursive,
+            AutoInstanceMeshRecursive,
+        ));
+    }
+    scene
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    args: Res<Args>,
+    keys: Res<KeyBindings>,
+    session: Option<Res<Session>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    time.set_relative_speed(args.time_scale);
+    commands.insert_resource(LoadPhase::Loading);
+
+    if args.no_mipmaps {
+        println!("Loading models without generating mipmaps");
+    } else {
+        println!(
+            "Loading models, generating mipmaps, {}x anisotropic filtering",
+            args.aniso
+        );
+    }
+
+    let mut watched_assets = WatchedAssets::new();
+
+    // San Miguel
+    let scene = spawn_scene(&mut commands, &asset_server, &args);
+    watched_assets.watch(scene, &args.scene);
+
+    // The sun and bounce lights are tuned for San Miguel
+    if args.review_lighting {
+        spawn_review_light(&mut commands);
+    } else if !args.keep_scene_lights {
+        spawn_light_rig(&mut commands, &args);
+    }
+
+    if args.fill_light {
+        // Turned around the vertical axis, so it shines from the other side at the same height
         commands.spawn((
-            SpotLightBundle {
-                transform: t.looking_at(Vec3::new(0.0, 999.0, 0.0), Vec3::X),
-                spot_light: SpotLight {
-                    range: 3.0,
-                    radius: 1.5,
-                    intensity: 150.0 * point_spot_mult,
-                    color: Color::rgb(1.0, 0.95, 0.9),
+            DirectionalLightBundle {
+                transform: Transform::from_rotation(
+                    Quat::from_rotation_y(PI) * sun_rotation(&args),
+                ),
+                directional_light: DirectionalLight {
+                    color: Color::rgb(0.8, 0.85, 1.0),
+                    illuminance: args.fill_intensity,
                     shadows_enabled: false,
-                    inner_angle: PI * 0.4,
-                    outer_angle: PI * 0.5,
                     ..default()
                 },
                 ..default()
             },
             GrifLight,
+            FillLight,
         ));
     }
 
+    let mut camera_controller = CameraController::default();
+    keys.apply_to(&mut camera_controller);
+    if let Some(speed) = args.cam_speed {
+        // Keep the run/walk ratio of the defaults
+        camera_controller.run_speed *= speed / camera_controller.walk_speed;
+        camera_controller.walk_speed = speed;
+    }
+    if let Some(sensitivity) = args.cam_sensitivity {
+        camera_controller.sensitivity = sensitivity;
+    }
+    camera_controller.orbit_focus = args.orbit_pivot;
+    camera_controller.max_roll = args.cam_max_roll.map(f32::to_radians);
+
+    let target = if args.bench_headless {
+        let (width, height) = args.resolution();
+        RenderTarget::Image(images.add(headless_render_target(width, height)))
+    } else {
+        RenderTarget::default()
+    };
+
+    // The camera isn't part of the scene so proc_scene won't remove it
+    let (transform, exposure, effects) = match &session {
+        Some(session) => (session.camera, session.exposure, session.effects),
+        None => (
+            Transform::from_xyz(-10.5, 1.7, -1.0).looking_at(SCENE_CENTER, Vec3::Y),
+            if args.review_lighting {
+                REVIEW_EXPOSURE
+            } else {
+                args.exposure
+            },
+            PostEffects::all(!args.minimal),
+        ),
+    };
+    let exposure = exposure.clamp(MIN_EXPOSURE, MAX_EXPOSURE);
+
     // Camera
     let mut cam = commands.spawn((
         Camera3dBundle {
             camera: Camera {
-                hdr: true,
+                hdr: !args.no_hdr,
+                target,
                 ..default()
             },
-            transform: Transform::from_xyz(-10.5, 1.7, -1.0)
-                .looking_at(Vec3::new(0.0, 3.5, 0.0), Vec3::Y),
+            transform,
             projection: Projection::Perspective(PerspectiveProjection {
-                fov: std::f32::consts::PI / 3.0,
+                fov: args.fov.to_radians(),
                 ..default()
             }),
+            tonemapping: args.tonemap,
             color_grading: ColorGrading {
                 #[cfg(not(feature = "bevy_main"))]
-                exposure: -2.0,
+                exposure,
                 #[cfg(feature = "bevy_main")]
                 global: bevy::render::view::ColorGradingGlobal {
-                    exposure: -2.0,
+                    exposure,
                     ..default()
                 },
                 ..default()
             },
             ..default()
         },
-        CameraController::default().print_controls(),
+        camera_controller.print_controls(),
     ));
 
-    if !args.minimal {
-        cam.insert((
-            BloomSettings {
-                intensity: 0.05,
-                ..default()
-            },
-            EnvironmentMapLight {
-                diffuse_map: asset_server.load("environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
-                specular_map: asset_server.load("environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
-                intensity: 1000.0,
+    if let Some(max_luminance) = args.clamp_luminance {
+        cam.insert(LuminanceClamp { max_luminance });
+    }
+    if args.auto_exposure {
+        cam.insert(AutoExposure {
+            speed: args.auto_exposure_speed,
+            min: args.auto_exposure_min,
+            max: args.auto_exposure_max,
+        });
+    }
+    if effects.bloom && args.no_hdr {
+        warn!("Bloom needs hdr, it's disabled with --no-hdr");
+    } else if effects.bloom {
+        cam.insert(bloom_settings(&args));
+    }
+    if effects.environment_map && !args.no_env_map && !args.review_lighting {
+        let env_map = environment_map(&asset_server, &args);
+        for handle in [&env_map.diffuse_map, &env_map.specular_map] {
+            let path = handle.path().map(|p| p.to_string()).unwrap_or_default();
+            watched_assets.watch(handle.clone(), path);
+        }
+        cam.insert(env_map);
+    }
+    if effects.ssao {
+        cam.insert(ScreenSpaceAmbientOcclusionBundle {
+            settings: ScreenSpaceAmbientOcclusionSettings {
+                quality_level: args.ssao_quality,
             },
-            TemporalAntiAliasBundle::default(),
-        ))
-        .insert(ScreenSpaceAmbientOcclusionBundle::default());
+            ..default()
+        });
+    }
+    if effects.anti_aliasing {
+        match args.aa {
+            AntiAliasing::Taa => {
+                cam.insert(TemporalAntiAliasBundle::default());
+            }
+            AntiAliasing::Fxaa => {
+                cam.insert(Fxaa::default());
+            }
+            AntiAliasing::None => (),
+        }
+    }
+
+    commands.insert_resource(watched_assets);
+}
+
+fn bloom_settings(args: &Args) -> BloomSettings {
+    let default = BloomSettings::default();
+    BloomSettings {
+        intensity: args.bloom_intensity,
+        low_frequency_boost: args
+            .bloom_low_frequency_boost
+            .unwrap_or(default.low_frequency_boost),
+        prefilter_settings: BloomPrefilterSettings {
+            threshold: args.bloom_threshold,
+            ..default.prefilter_settings
+        },
+        ..default
+    }
+}
+
+fn environment_map(asset_server: &AssetServer, args: &Args) -> EnvironmentMapLight {
+    EnvironmentMapLight {
+        diffuse_map: asset_server.load(args.env_diffuse.clone()),
+        specular_map: asset_server.load(args.env_specular.clone()),
+        intensity: args.env_intensity,
     }
 }
 
+fn headless_render_target(width: u32, height: u32) -> Image {
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
 pub fn all_children<F: FnMut(Entity)>(
     children: &Children,
     children_query: &Query<&Children>,
@@ -307,13 +1753,17 @@ pub fn all_children<F: FnMut(Entity)>(
     }
 }
 
-#[allow(clippy::type_complexity)]
+// Entities proc_scene visits each frame, so large scenes don't stall the frame they spawn in
+const PROC_SCENE_ENTITIES_PER_FRAME: usize = 500;
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn proc_scene(
     mut commands: Commands,
     materials_query: Query<Entity, With<PostProcScene>>,
     children_query: Query<&Children>,
     has_std_mat: Query<&Handle<StandardMaterial>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    transmission: Res<TransmissionSettings>,
     lights: Query<
         Entity,
         (
@@ -322,117 +1772,733 @@ pub fn proc_scene(
         ),
     >,
     cameras: Query<Entity, With<Camera>>,
+    args: Res<Args>,
+    mut phase: ResMut<LoadPhase>,
+    material_overrides: Option<Res<MaterialOverrides>>,
+    mut debug_colors: Option<ResMut<DebugMaterialColors>>,
+    mut pending: Local<HashMap<Entity, Vec<Entity>>>,
 ) {
-    for entity in materials_query.iter() {
-        if let Ok(children) = children_query.get(entity) {
-            all_children(children, &children_query, &mut |entity| {
-                if let Ok(mat_h) = has_std_mat.get(entity) {
-                    if let Some(mat) = materials.get_mut(mat_h) {
-                        match mat.alpha_mode {
-                            AlphaMode::Mask(_) => {
-                                mat.diffuse_transmission = 0.6;
-                                mat.double_sided = true;
-                                mat.cull_mode = None;
-                                mat.thickness = 0.2;
-                                commands.entity(entity).insert(TransmittedShadowReceiver);
-                            }
-                            _ => (),
+    if materials_query.is_empty() {
+        phase.set_if_neq(LoadPhase::Rendering);
+    }
+    // Drop the rest of scenes that were despawned before they finished
+    pending.retain(|root, _| materials_query.contains(*root));
+    let mut budget = PROC_SCENE_ENTITIES_PER_FRAME;
+    for root in materials_query.iter() {
+        if budget == 0 {
+            break;
+        }
+        if !pending.contains_key(&root) {
+            let Ok(children) = children_query.get(root) else {
+                continue;
+            };
+            pending.insert(root, children.to_vec());
+        }
+        let stack = pending.get_mut(&root).unwrap();
+        while budget > 0 {
+            let Some(entity) = stack.pop() else {
+                break;
+            };
+            budget -= 1;
+            if let Ok(children) = children_query.get(entity) {
+                stack.extend(children);
+            }
+            if let (true, Ok(mat_h)) = (args.opaque_only, has_std_mat.get(entity)) {
+                if let Some(mat) = materials.get_mut(mat_h) {
+                    mat.alpha_mode = AlphaMode::Opaque;
+                    mat.diffuse_transmission = 0.0;
+                    mat.specular_transmission = 0.0;
+                }
+            }
+            if let (true, Ok(mat_h)) = (transmission.enabled, has_std_mat.get(entity)) {
+                if let Some(mat) = materials.get_mut(mat_h) {
+                    #[allow(clippy::single_match)]
+                    match mat.alpha_mode {
+                        AlphaMode::Mask(_) => {
+                            mat.diffuse_transmission = transmission.diffuse_transmission;
+                            mat.double_sided = true;
+                            mat.cull_mode = None;
+                            mat.thickness = transmission.thickness;
+                            commands.entity(entity).insert(TransmittedShadowReceiver);
                         }
+                        _ => (),
                     }
                 }
-
-                // Remove Default Lights
-                if lights.get(entity).is_ok() {
-                    commands.entity(entity).despawn_recursive();
+            }
+            if let (Some(overrides), Ok(mat_h)) = (&material_overrides, has_std_mat.get(entity)) {
+                if let (Some(material_override), Some(mat)) =
+                    (overrides.get(mat_h), materials.get_mut(mat_h))
+                {
+                    material_override.apply(mat);
                 }
-
-                // Remove Default Cameras
-                if cameras.get(entity).is_ok() {
-                    commands.entity(entity).despawn_recursive();
+            }
+            if let (Some(debug_colors), Ok(mat_h)) = (&mut debug_colors, has_std_mat.get(entity)) {
+                if let Some(mat) = materials.get_mut(mat_h) {
+                    debug_colors.recolor(mat_h, mat);
                 }
-            });
-            commands.entity(entity).remove::<PostProcScene>();
+            }
+
+            // Remove Default Lights
+            if !args.keep_scene_lights && lights.get(entity).is_ok() {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            // Remove Default Cameras
+            if cameras.get(entity).is_ok() {
+                commands.entity(entity).despawn_recursive();
+            }
         }
+        if stack.is_empty() {
+            pending.remove(&root);
+            commands.entity(root).remove::<PostProcScene>();
+        }
+    }
+}
+
+pub const DEFAULT_SCENE: &str = "san-miguel/san-miguel.gltf";
+pub const DEFAULT_ENV_DIFFUSE: &str = "environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2";
+pub const DEFAULT_ENV_SPECULAR: &str = "environment_maps/pisa_specular_rgb9e5_zstd.ktx2";
+
+/// The gltf or glb file of a --scene path, without its label
+pub fn scene_file(path: &str) -> &str {
+    path.split('#').next().unwrap()
+}
+
+/// Loads the first scene of the gltf or glb unless `path` already names one with a `#Scene` label
+fn scene_asset_path(path: &str) -> String {
+    if path.contains('#') {
+        path.to_string()
+    } else {
+        format!("{path}#Scene0")
     }
 }
 
-const CAM_POS_1: Transform = Transform {
+/// Center of the courtyard, the starting camera looks at it and orbit mode circles it
+pub const SCENE_CENTER: Vec3 = Vec3::new(0.0, 3.5, 0.0);
+
+pub const CAM_POS_1: Transform = Transform {
     translation: Vec3::new(-10.5, 1.7, -1.0),
     rotation: Quat::from_array([-0.05678932, 0.7372272, -0.062454797, -0.670351]),
     scale: Vec3::ONE,
 };
 
-const CAM_POS_2: Transform = Transform {
+pub const CAM_POS_2: Transform = Transform {
     translation: Vec3::new(4.8306146, 1.5906956, 12.70758),
     rotation: Quat::from_array([-0.02797842, -0.3963449, 0.012084955, -0.91759574]),
     scale: Vec3::ONE,
 };
 
-const CAM_POS_3: Transform = Transform {
+pub const CAM_POS_3: Transform = Transform {
     translation: Vec3::new(-14.211411, 6.807057, 1.6095632),
     rotation: Quat::from_array([0.0014981055, 0.71061265, 0.0015130794, -0.7035802]),
     scale: Vec3::ONE,
 };
 
-fn input(input: Res<ButtonInput<KeyCode>>, mut camera: Query<&mut Transform, With<Camera>>) {
-    let Ok(mut transform) = camera.get_single_mut() else {
+const MIN_EXPOSURE: f32 = -8.0;
+const MAX_EXPOSURE: f32 = 8.0;
+const EXPOSURE_STEP: f32 = 0.1;
+
+pub fn exposure_mut(color_grading: &mut ColorGrading) -> &mut f32 {
+    #[cfg(not(feature = "bevy_main"))]
+    {
+        &mut color_grading.exposure
+    }
+    #[cfg(feature = "bevy_main")]
+    {
+        &mut color_grading.global.exposure
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn input(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut camera: Query<(Entity, &mut Transform), (With<Camera>, Without<MinimapCamera>)>,
+    camera_positions: Res<CameraPositions>,
+    mut recorded_positions: ResMut<RecordedCameraPositions>,
+    mut sun: Query<&mut DirectionalLight, With<SunLight>>,
+    mut color_grading: Query<&mut ColorGrading, (With<Camera>, Without<MinimapCamera>)>,
+    mut screenshots: EventWriter<CaptureScreenshot>,
+    mut current_position: Local<Option<usize>>,
+    args: Res<Args>,
+    scene_bounds: Option<Res<SceneBounds>>,
+    auto_exposure: Query<Entity, With<AutoExposure>>,
+    mut clipboard: NonSendMut<Clipboard>,
+) {
+    if input.any_just_pressed(keys.screenshot.iter().copied()) {
+        screenshots.send(CaptureScreenshot {
+            position: *current_position,
+        });
+    }
+    let exposure_change = if input.just_pressed(keys.exposure_down) {
+        -EXPOSURE_STEP
+    } else if input.just_pressed(keys.exposure_up) {
+        EXPOSURE_STEP
+    } else {
+        0.0
+    };
+    if exposure_change != 0.0 {
+        for entity in &auto_exposure {
+            commands.entity(entity).remove::<AutoExposure>();
+            println!("Auto exposure: off");
+        }
+        for mut color_grading in &mut color_grading {
+            let exposure = exposure_mut(&mut color_grading);
+            *exposure = (*exposure + exposure_change).clamp(MIN_EXPOSURE, MAX_EXPOSURE);
+            println!("Exposure: {:.1}", *exposure);
+        }
+    }
+    if input.just_pressed(keys.toggle_sun_shadows) {
+        for mut light in &mut sun {
+            // Cascades are re-rendered from scratch every frame they are enabled,
+            // so nothing from before turning them off is reused
+            light.shadows_enabled = !light.shadows_enabled;
+            println!(
+                "Sun shadows: {}",
+                if light.shadows_enabled { "on" } else { "off" }
+            );
+        }
+    }
+    let Ok((entity, mut transform)) = camera.get_single_mut() else {
         return;
     };
-    if input.just_pressed(KeyCode::KeyI) {
+    if input.just_pressed(keys.print_transform) {
         info!("{:?}", transform);
+        recorded_positions.push(*transform);
+        if let Some(bounds) = &scene_bounds {
+            bounds.print();
+        }
+    }
+    if input.just_pressed(keys.copy_transform) {
+        let literal = transform_literal(&transform);
+        println!("{literal}");
+        match clipboard.copy(&literal) {
+            Ok(()) => info!("Copied the camera transform to the clipboard"),
+            Err(e) => warn!("Failed to copy the camera transform to the clipboard: {e:#}"),
+        }
     }
-    if input.just_pressed(KeyCode::Digit1) {
-        *transform = CAM_POS_1
+    if input.just_pressed(keys.save_cam_positions) {
+        let positions = CameraPositions(recorded_positions.clone());
+        match positions.save(RECORDED_CAMERA_POSITIONS_PATH) {
+            Ok(()) => info!(
+                "Saved {} camera positions to {RECORDED_CAMERA_POSITIONS_PATH}",
+                positions.len()
+            ),
+            Err(e) => warn!("Failed to save camera positions: {e:#}"),
+        }
     }
-    if input.just_pressed(KeyCode::Digit2) {
-        *transform = CAM_POS_2
+    let mut selected = keys
+        .cam_positions
+        .iter()
+        .take(camera_positions.len())
+        .position(|key| input.just_pressed(*key));
+    if input.just_pressed(keys.next_cam_pos) && !camera_positions.is_empty() {
+        selected = Some(current_position.map_or(0, |i| (i + 1) % camera_positions.len()));
     }
-    if input.just_pressed(KeyCode::Digit3) {
-        *transform = CAM_POS_3
+    if let Some(i) = selected {
+        *current_position = Some(i);
+        move_camera_to(
+            &mut commands,
+            entity,
+            &mut transform,
+            camera_positions[i],
+            args.cam_smooth,
+        );
     }
 }
 
-fn benchmark(
+/// F5: Bloom, F6: SSAO, F7: Anti-aliasing (the --aa method), F8: Environment map.
+/// Removed components are kept around so toggling back on restores their settings.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn toggle_post_effects(
+    mut commands: Commands,
     input: Res<ButtonInput<KeyCode>>,
-    mut camera: Query<&mut Transform, With<Camera>>,
-    mut bench_started: Local<Option<Instant>>,
-    mut bench_frame: Local<u32>,
-    mut count_per_step: Local<u32>,
-    time: Res<Time>,
+    keys: Res<KeyBindings>,
+    asset_server: Res<AssetServer>,
+    args: Res<Args>,
+    camera: Query<
+        (
+            Entity,
+            Option<&BloomSettings>,
+            Option<&ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+            Has<Fxaa>,
+            Option<&EnvironmentMapLight>,
+        ),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut removed_bloom: Local<Option<BloomSettings>>,
+    mut removed_ssao: Local<Option<ScreenSpaceAmbientOcclusionSettings>>,
+    mut removed_env_map: Local<Option<EnvironmentMapLight>>,
 ) {
-    if input.just_pressed(KeyCode::KeyB) && bench_started.is_none() {
-        *bench_started = Some(Instant::now());
-        *bench_frame = 0;
-        // Try to render for around 2s or at least 30 frames per step
-        *count_per_step = ((2.0 / time.delta_seconds()) as u32).max(30);
+    let Ok((entity, bloom, ssao, taa, fxaa, env_map)) = camera.get_single() else {
+        return;
+    };
+    let (mut bloom_on, mut ssao_on, mut aa_on, mut env_map_on) = (
+        bloom.is_some(),
+        ssao.is_some(),
+        taa || fxaa,
+        env_map.is_some(),
+    );
+    let mut cam = commands.entity(entity);
+    let mut changed = false;
+    if input.just_pressed(keys.toggle_bloom) && args.no_hdr {
+        warn!("Bloom needs hdr, it can't be enabled with --no-hdr");
+    } else if input.just_pressed(keys.toggle_bloom) {
+        if let Some(bloom) = bloom {
+            *removed_bloom = Some(bloom.clone());
+            cam.remove::<BloomSettings>();
+        } else {
+            cam.insert(
+                removed_bloom
+                    .take()
+                    .unwrap_or_else(|| bloom_settings(&args)),
+            );
+        }
+        bloom_on = !bloom_on;
+        changed = true;
+    }
+    if input.just_pressed(keys.toggle_ssao) {
+        if let Some(ssao) = ssao {
+            *removed_ssao = Some(ssao.clone());
+            cam.remove::<ScreenSpaceAmbientOcclusionSettings>();
+        } else {
+            cam.insert(ScreenSpaceAmbientOcclusionBundle {
+                settings: removed_ssao
+                    .take()
+                    .unwrap_or(ScreenSpaceAmbientOcclusionSettings {
+                        quality_level: args.ssao_quality,
+                    }),
+                ..default()
+            });
+        }
+        ssao_on = !ssao_on;
+        changed = true;
+    }
+    if input.just_pressed(keys.toggle_aa) {
+        match args.aa {
+            AntiAliasing::Taa if taa => {
+                cam.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+            }
+            AntiAliasing::Taa => {
+                cam.insert(TemporalAntiAliasBundle::default());
+            }
+            AntiAliasing::Fxaa if fxaa => {
+                cam.remove::<Fxaa>();
+            }
+            AntiAliasing::Fxaa => {
+                cam.insert(Fxaa::default());
+            }
+            AntiAliasing::None => println!("No anti-aliasing method selected, see --aa"),
+        }
+        if args.aa != AntiAliasing::None {
+            aa_on = !aa_on;
+            changed = true;
+        }
+    }
+    if input.just_pressed(keys.toggle_env_map) && args.no_env_map {
+        println!("Environment map is disabled with --no-env-map");
+    } else if input.just_pressed(keys.toggle_env_map) {
+        if let Some(env_map) = env_map {
+            *removed_env_map = Some(env_map.clone());
+            cam.remove::<EnvironmentMapLight>();
+        } else {
+            cam.insert(
+                removed_env_map
+                    .take()
+                    .unwrap_or_else(|| environment_map(&asset_server, &args)),
+            );
+        }
+        env_map_on = !env_map_on;
+        changed = true;
+    }
+    if changed {
+        let state = |on: bool| if on { "on" } else { "off" };
         println!(
-            "Starting Benchmark with {} frames per step",
-            *count_per_step
+            "Bloom: {}, SSAO: {}, AA ({:?}): {}, Environment map: {}",
+            state(bloom_on),
+            state(ssao_on),
+            args.aa,
+            state(aa_on),
+            state(env_map_on)
         );
     }
-    if bench_started.is_none() {
+}
+
+const BLOOM_INTENSITY_STEP: f32 = 0.01;
+
+/// ; and ': bloom intensity
+fn adjust_bloom(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut bloom: Query<&mut BloomSettings, (With<Camera>, Without<MinimapCamera>)>,
+) {
+    let change = if input.just_pressed(keys.bloom_down) {
+        -BLOOM_INTENSITY_STEP
+    } else if input.just_pressed(keys.bloom_up) {
+        BLOOM_INTENSITY_STEP
+    } else {
         return;
+    };
+    for mut bloom in &mut bloom {
+        bloom.intensity = (bloom.intensity + change).clamp(0.0, 1.0);
+        println!("Bloom intensity: {:.2}", bloom.intensity);
     }
-    let Ok(mut transform) = camera.get_single_mut() else {
-        return;
+}
+
+const SHADOW_DEPTH_BIAS_STEP: f32 = 0.005;
+const SHADOW_NORMAL_BIAS_STEP: f32 = 0.1;
+
+/// Y/U: sun shadow depth bias, G/H: sun shadow normal bias
+fn adjust_shadow_bias(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut sun: Query<&mut DirectionalLight, With<SunLight>>,
+) {
+    let step = |down: KeyCode, up: KeyCode, step: f32| {
+        if input.just_pressed(down) {
+            -step
+        } else if input.just_pressed(up) {
+            step
+        } else {
+            0.0
+        }
     };
-    if *bench_frame == 0 {
-        *transform = CAM_POS_1
-    } else if *bench_frame == *count_per_step {
-        *transform = CAM_POS_2
-    } else if *bench_frame == *count_per_step * 2 {
-        *transform = CAM_POS_3
-    } else if *bench_frame == *count_per_step * 3 {
-        let elapsed = bench_started.unwrap().elapsed().as_secs_f32();
+    let depth_change = step(
+        keys.shadow_depth_bias_down,
+        keys.shadow_depth_bias_up,
+        SHADOW_DEPTH_BIAS_STEP,
+    );
+    let normal_change = step(
+        keys.shadow_normal_bias_down,
+        keys.shadow_normal_bias_up,
+        SHADOW_NORMAL_BIAS_STEP,
+    );
+    if depth_change == 0.0 && normal_change == 0.0 {
+        return;
+    }
+    for mut light in &mut sun {
+        light.shadow_depth_bias = (light.shadow_depth_bias + depth_change).max(0.0);
+        light.shadow_normal_bias = (light.shadow_normal_bias + normal_change).max(0.0);
         println!(
-            "Benchmark avg cpu frame time: {:.2}ms",
-            (elapsed / *bench_frame as f32) * 1000.0
+            "Sun shadow depth bias: {:.3}, normal bias: {:.2}",
+            light.shadow_depth_bias, light.shadow_normal_bias
         );
-        *bench_started = None;
-        *bench_frame = 0;
-        *transform = CAM_POS_1;
     }
-    *bench_frame += 1;
+}
+
+// Degrees per second
+const SUN_ROTATION_SPEED: f32 = 20.0;
+const SUN_YAW: f32 = PI * -0.08;
+
+/// Loops the sun pitch through the --sun-animate range, paused while benchmarking
+fn sun_animate(
+    time: Res<Time>,
+    args: Res<Args>,
+    benchmark: Res<BenchmarkRunning>,
+    mut sun: Query<&mut Transform, With<SunLight>>,
+    mut elapsed: Local<f32>,
+) {
+    let Some(period) = args.sun_animate else {
+        return;
+    };
+    if benchmark.0 {
+        return;
+    }
+    *elapsed = (*elapsed + time.delta_seconds()) % period;
+    let pitch = args
+        .sun_animate_from
+        .lerp(args.sun_animate_to, *elapsed / period)
+        .to_radians();
+    for mut transform in &mut sun {
+        transform.rotation = Quat::from_euler(EulerRot::XYZ, pitch, SUN_YAW, 0.0);
+    }
+}
+
+fn sun_control(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    time: Res<Time>,
+    mut sun: Query<&mut Transform, With<SunLight>>,
+) {
+    let Ok(mut transform) = sun.get_single_mut() else {
+        return;
+    };
+    let mut direction = 0.0;
+    if input.pressed(keys.sun_down) {
+        direction -= 1.0;
+    }
+    if input.pressed(keys.sun_up) {
+        direction += 1.0;
+    }
+    if direction != 0.0 {
+        // Same axis as the pitch in setup
+        transform.rotate_x((direction * SUN_ROTATION_SPEED * time.delta_seconds()).to_radians());
+    }
+    if input.any_just_released([keys.sun_down, keys.sun_up]) {
+        let (pitch, _, _) = transform.rotation.to_euler(EulerRot::XYZ);
+        info!("Sun angle: {:.1}", pitch.to_degrees());
+    }
+}
+
+const MIN_TIME_SCALE: f32 = 1.0 / 64.0;
+const MAX_TIME_SCALE: f32 = 8.0;
+
+/// Run condition of the systems driven by virtual time, like the sun animation, camera tweens
+/// and auto exposure, so they stay exactly where they are while P has it paused
+pub fn time_running(time: Res<Time<Virtual>>) -> bool {
+    !time.is_paused()
+}
+
+/// Z and X: halve and double the speed of virtual time, P: pause it.
+/// The camera controller runs on real time so the view can still be moved while it's frozen.
+fn adjust_time_scale(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut time: ResMut<Time<Virtual>>,
+    benchmark: Res<BenchmarkRunning>,
+    mut tweening: Query<&mut CameraController, With<CameraTween>>,
+) {
+    if input.just_pressed(keys.pause) {
+        if time.is_paused() {
+            time.unpause();
+            println!("RESUMED");
+        } else {
+            time.pause();
+            println!("PAUSED");
+            // A paused tween would keep the controller disabled, hand the camera back until the
+            // tween continues on resume
+            if !benchmark.0 {
+                for mut controller in &mut tweening {
+                    controller.enabled = true;
+                    controller.initialized = false;
+                    controller.velocity = Vec3::ZERO;
+                }
+            }
+        }
+    }
+    let speed = time.relative_speed();
+    let speed = if input.just_pressed(keys.time_slower) {
+        (speed * 0.5).max(MIN_TIME_SCALE)
+    } else if input.just_pressed(keys.time_faster) {
+        // Speeding up from a --time-scale 0 freeze starts at the slowest step
+        (speed * 2.0).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+    } else {
+        return;
+    };
+    time.set_relative_speed(speed);
+    println!("Time scale: {speed}");
+}
+
+const BOUNCE_LIGHT_SCALE_STEP: f32 = 1.1;
+
+fn scale_bounce_lights(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut scale: ResMut<BounceLightScale>,
+    mut spot_lights: Query<(&mut SpotLight, &BaseIntensity), With<GrifLight>>,
+    mut point_lights: Query<(&mut PointLight, &BaseIntensity), With<GrifLight>>,
+) {
+    if input.just_pressed(keys.bounce_lights_down) {
+        scale.0 /= BOUNCE_LIGHT_SCALE_STEP;
+    } else if input.just_pressed(keys.bounce_lights_up) {
+        scale.0 *= BOUNCE_LIGHT_SCALE_STEP;
+    } else {
+        return;
+    }
+    // Always scale from the authored intensity so repeated changes don't drift
+    for (mut light, base) in &mut spot_lights {
+        light.intensity = base.0 * scale.0;
+    }
+    for (mut light, base) in &mut point_lights {
+        light.intensity = base.0 * scale.0;
+    }
+    println!("Bounce light scale: {:.2}", scale.0);
+}
+
+/// F11: cycle MSAA through off, 2x, 4x and 8x, skipping sample counts the gpu doesn't support.
+/// TAA and SSAO need MSAA off, so they are removed while it's on and restored when it's off again.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn cycle_msaa(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut msaa: ResMut<Msaa>,
+    adapter: Res<RenderAdapter>,
+    camera: Query<
+        (
+            Entity,
+            Has<TemporalAntiAliasSettings>,
+            Option<&ScreenSpaceAmbientOcclusionSettings>,
+        ),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut removed_taa: Local<bool>,
+    mut removed_ssao: Local<Option<ScreenSpaceAmbientOcclusionSettings>>,
+) {
+    if !input.just_pressed(keys.cycle_msaa) {
+        return;
+    }
+    let Ok((entity, taa, ssao)) = camera.get_single() else {
+        return;
+    };
+    let supported = adapter
+        .get_texture_format_features(ViewTarget::TEXTURE_FORMAT_HDR)
+        .flags;
+    let modes = [Msaa::Off, Msaa::Sample2, Msaa::Sample4, Msaa::Sample8];
+    let current = modes.iter().position(|mode| mode == &*msaa).unwrap_or(0);
+    *msaa = (1..modes.len())
+        .map(|offset| modes[(current + offset) % modes.len()])
+        .find(|mode| supported.sample_count_supported(mode.samples()))
+        .unwrap_or(Msaa::Off);
+
+    let mut cam = commands.entity(entity);
+    if *msaa == Msaa::Off {
+        if std::mem::take(&mut *removed_taa) {
+            cam.insert(TemporalAntiAliasBundle::default());
+        }
+        if let Some(ssao) = removed_ssao.take() {
+            cam.insert(ssao);
+        }
+    } else {
+        if taa {
+            *removed_taa = true;
+            cam.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+        }
+        if let Some(ssao) = ssao {
+            *removed_ssao = Some(ssao.clone());
+            cam.remove::<ScreenSpaceAmbientOcclusionSettings>();
+        }
+    }
+    println!("MSAA: {:?}", *msaa);
+}
+
+/// T: cycle through the tonemapping methods to compare them
+fn cycle_tonemapping(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut camera: Query<&mut Tonemapping, (With<Camera>, Without<MinimapCamera>)>,
+) {
+    if !input.just_pressed(keys.cycle_tonemapping) {
+        return;
+    }
+    for mut tonemapping in &mut camera {
+        let current = TONEMAPPING_METHODS
+            .iter()
+            .position(|(_, method)| *method == *tonemapping)
+            .unwrap_or(0);
+        let (name, next) = TONEMAPPING_METHODS[(current + 1) % TONEMAPPING_METHODS.len()];
+        *tonemapping = next;
+        println!("Tonemapping: {name}");
+    }
+}
+
+/// L: cycle through the ssao quality levels, to see what the higher ones cost
+fn cycle_ssao_quality(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    mut camera: Query<
+        &mut ScreenSpaceAmbientOcclusionSettings,
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+) {
+    if !input.just_pressed(keys.cycle_ssao_quality) {
+        return;
+    }
+    for mut ssao in &mut camera {
+        let current = SSAO_QUALITY_LEVELS
+            .iter()
+            .position(|(_, quality)| *quality == ssao.quality_level)
+            .unwrap_or(0);
+        let (name, next) = SSAO_QUALITY_LEVELS[(current + 1) % SSAO_QUALITY_LEVELS.len()];
+        ssao.quality_level = next;
+        println!("SSAO quality: {name}");
+    }
+}
+
+/// F9: switch between perspective and orthographic projection.
+/// The orthographic view is sized to match the perspective one at the orbit pivot.
+#[allow(clippy::type_complexity)]
+fn toggle_projection(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    args: Res<Args>,
+    mut camera: Query<
+        (&Transform, &mut Projection, Option<&CameraController>),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+) {
+    if !input.just_pressed(keys.toggle_projection) {
+        return;
+    }
+    let Ok((transform, mut projection, controller)) = camera.get_single_mut() else {
+        return;
+    };
+    let fov = args.fov.to_radians();
+    *projection = match *projection {
+        Projection::Perspective(_) => {
+            let distance = controller
+                .map_or(SCENE_CENTER, |controller| controller.orbit_focus)
+                .distance(transform.translation)
+                .max(1.0);
+            println!("Projection: orthographic");
+            Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::FixedVertical(2.0 * distance * (fov * 0.5).tan()),
+                ..default()
+            })
+        }
+        Projection::Orthographic(_) => {
+            println!("Projection: perspective");
+            Projection::Perspective(PerspectiveProjection { fov, ..default() })
+        }
+    };
+}
+
+/// F4: wireframe on the scene meshes, with --wireframe
+fn toggle_wireframe(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    scenes: Query<&Children, With<Handle<Scene>>>,
+    children_query: Query<&Children>,
+    meshes: Query<(), With<Handle<Mesh>>>,
+    mut enabled: Local<bool>,
+) {
+    if !input.just_pressed(keys.toggle_wireframe) {
+        return;
+    }
+    *enabled = !*enabled;
+    for children in &scenes {
+        all_children(children, &children_query, &mut |entity| {
+            if meshes.get(entity).is_ok() {
+                if *enabled {
+                    commands.entity(entity).insert(Wireframe);
+                } else {
+                    commands.entity(entity).remove::<Wireframe>();
+                }
+            }
+        });
+    }
+    println!("Wireframe: {}", if *enabled { "on" } else { "off" });
+}
+
+/// Sleeps off the rest of the frame to stay under --max-fps
+fn limit_frame_rate(
+    args: Res<Args>,
+    benchmark: Res<BenchmarkRunning>,
+    mut frame_end: Local<Option<Instant>>,
+) {
+    let Some(max_fps) = args.max_fps else {
+        return;
+    };
+    if !benchmark.0 {
+        let frame_time = Duration::from_secs_f64(1.0 / max_fps as f64);
+        if let Some(remaining) = frame_end.and_then(|end| frame_time.checked_sub(end.elapsed())) {
+            std::thread::sleep(remaining);
+        }
+    }
+    *frame_end = Some(Instant::now());
 }
 
 pub fn add_no_frustum_culling(
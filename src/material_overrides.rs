@@ -0,0 +1,126 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use bevy::{prelude::*, utils::HashMap};
+use ron::extensions::Extensions;
+use serde::{de::Error, Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::{export_gltf::material_index, parse_color, scene_file};
+
+/// Properties set on the materials with a gltf material name, the ones left out keep their
+/// value. Colors are written like the color flags, e.g. `(base_color: "#ffd27a", metallic: 1.0)`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialOverride {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub base_color: Option<Color>,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub emissive: Option<Color>,
+}
+
+impl MaterialOverride {
+    pub fn apply(&self, material: &mut StandardMaterial) {
+        if let Some(base_color) = self.base_color {
+            material.base_color = base_color;
+        }
+        if let Some(metallic) = self.metallic {
+            material.metallic = metallic;
+        }
+        if let Some(roughness) = self.roughness {
+            material.perceptual_roughness = roughness;
+        }
+        if let Some(emissive) = self.emissive {
+            #[cfg(not(feature = "bevy_main"))]
+            {
+                material.emissive = emissive;
+            }
+            #[cfg(feature = "bevy_main")]
+            {
+                material.emissive = emissive.into();
+            }
+        }
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|color| parse_color(&color).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// The overrides from `--material-overrides <path>`, by the index of the gltf material they
+/// apply to. [`crate::proc_scene`] applies them after its transmission changes, R reads the file
+/// again along with the scene.
+#[derive(Resource, Default)]
+pub struct MaterialOverrides(HashMap<usize, MaterialOverride>);
+
+impl MaterialOverrides {
+    pub fn load(path: impl AsRef<Path>, scene: &str) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read material overrides {}", path.display()))?;
+        let by_name: HashMap<String, MaterialOverride> = ron::Options::default()
+            .with_default_extension(Extensions::IMPLICIT_SOME)
+            .from_str(&contents)
+            .with_context(|| format!("Failed to parse material overrides {}", path.display()))?;
+
+        let names = material_names(&Path::new("assets").join(scene_file(scene)))?;
+        let mut overrides = HashMap::new();
+        for (name, material_override) in by_name {
+            let mut found = false;
+            let indices = names
+                .iter()
+                .enumerate()
+                .filter(|(_, material)| material.as_deref() == Some(&*name));
+            for (index, _) in indices {
+                overrides.insert(index, material_override.clone());
+                found = true;
+            }
+            if !found {
+                eprintln!("There's no material named {name} in {scene}, its override is ignored");
+            }
+        }
+        Ok(Self(overrides))
+    }
+
+    pub fn get(&self, handle: &Handle<StandardMaterial>) -> Option<&MaterialOverride> {
+        self.by_index(material_index(handle)?)
+    }
+
+    /// The override of the gltf material at `index`
+    pub fn by_index(&self, index: usize) -> Option<&MaterialOverride> {
+        self.0.get(&index)
+    }
+}
+
+/// Name of each material in the gltf or glb, in the order the loader numbers them
+fn material_names(scene: &Path) -> anyhow::Result<Vec<Option<String>>> {
+    let contents =
+        fs::read(scene).with_context(|| format!("Failed to read {}", scene.display()))?;
+    let json = if scene.extension().is_some_and(|ext| ext == "glb") {
+        glb_json(&contents).with_context(|| format!("Failed to read {}", scene.display()))?
+    } else {
+        &contents[..]
+    };
+    let gltf: Value = serde_json::from_slice(json)
+        .with_context(|| format!("Failed to parse {}", scene.display()))?;
+    let materials = gltf["materials"].as_array().map_or(&[][..], Vec::as_slice);
+    Ok(materials
+        .iter()
+        .map(|material| material["name"].as_str().map(str::to_string))
+        .collect())
+}
+
+/// The json chunk of a glb, which comes first after the 12 byte header
+fn glb_json(glb: &[u8]) -> anyhow::Result<&[u8]> {
+    if glb.get(0..4) != Some(&b"glTF"[..]) || glb.get(16..20) != Some(&b"JSON"[..]) {
+        bail!("not a glb file");
+    }
+    let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    glb.get(20..20 + length).context("the file is truncated")
+}
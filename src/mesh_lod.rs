@@ -0,0 +1,254 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        primitives::Aabb,
+        render_resource::PrimitiveTopology,
+    },
+    utils::{HashMap, HashSet},
+};
+use meshopt::VertexDataAdapter;
+
+use crate::{
+    benchmark::SceneLoadState,
+    mesh_optimizer::{first_use_order, optimize_meshes, reorder_vertices},
+    minimap::MinimapCamera,
+};
+
+/// Share of the triangles each simplified level aims to keep, and the largest error the
+/// simplification may introduce for it, relative to the size of the mesh
+const LOD_TARGETS: [(f32, f32); 2] = [(0.5, 0.01), (0.2, 0.04)];
+/// A level is only kept if it has at most this share of the previous level's triangles
+const LOD_MIN_REDUCTION: f32 = 0.9;
+/// Distance from the camera at which each simplified level is used, in bounding radii of the
+/// mesh, so large meshes keep their detail further away than small ones
+const LOD_DISTANCES: [f32; 2] = [25.0, 60.0];
+/// How far past a switch distance the camera has to move before the level changes, as a share
+/// of that distance, so meshes near it don't pop back and forth
+const LOD_HYSTERESIS: f32 = 0.15;
+
+/// The simplified levels generated for a mesh and the triangle count of each level, including
+/// the full one. A level that didn't simplify enough is None and reuses the previous one.
+struct LodLevels {
+    handles: [Option<Handle<Mesh>>; 2],
+    triangles: [usize; 3],
+}
+
+#[derive(Resource, Default)]
+struct MeshLods {
+    levels: HashMap<AssetId<Mesh>, LodLevels>,
+    /// Meshes added as levels, so they aren't simplified again
+    generated: HashSet<AssetId<Mesh>>,
+}
+
+/// The levels of an entity's mesh, full detail first, and the one it currently draws
+#[derive(Component)]
+struct MeshLod {
+    handles: [Handle<Mesh>; 3],
+    triangles: [usize; 3],
+    level: usize,
+}
+
+/// Triangles of the visible meshes that have levels, at full detail and as drawn
+#[derive(Resource, Default)]
+pub struct LodStats {
+    pub full: usize,
+    pub drawn: usize,
+}
+
+/// With --lod, generates two simplified levels of each mesh as it's loaded and swaps an entity's
+/// mesh for them as it gets further from the camera
+pub struct MeshLodPlugin;
+
+impl Plugin for MeshLodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeshLods>()
+            .init_resource::<LodStats>()
+            .add_systems(
+                Update,
+                (generate_mesh_lods.after(optimize_meshes), select_mesh_lods).chain(),
+            );
+    }
+}
+
+#[derive(Default)]
+struct GeneratedLods {
+    count: u32,
+    triangles: [usize; 3],
+}
+
+/// Simplifies each mesh as it's added, prints the triangle totals of each level once the scene
+/// is loaded
+fn generate_mesh_lods(
+    mut events: EventReader<AssetEvent<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut lods: ResMut<MeshLods>,
+    scene: SceneLoadState,
+    mut generated: Local<GeneratedLods>,
+) {
+    for event in events.read() {
+        let id = match event {
+            AssetEvent::Added { id } => *id,
+            AssetEvent::Removed { id } => {
+                lods.levels.remove(id);
+                lods.generated.remove(id);
+                continue;
+            }
+            _ => continue,
+        };
+        if lods.generated.contains(&id) {
+            continue;
+        }
+        let Some((simplified, triangles)) = meshes.get(id).and_then(simplify_mesh) else {
+            continue;
+        };
+        let handles = simplified.map(|mesh| mesh.map(|mesh| meshes.add(mesh)));
+        for handle in handles.iter().flatten() {
+            lods.generated.insert(handle.id());
+        }
+        lods.levels.insert(id, LodLevels { handles, triangles });
+        generated.count += 1;
+        for (total, level) in generated.triangles.iter_mut().zip(triangles) {
+            *total += level;
+        }
+    }
+    if generated.count == 0 || !scene.is_loaded() {
+        return;
+    }
+    let [full, lod1, lod2] = generated.triangles;
+    let percent = |triangles: usize| triangles as f32 / full.max(1) as f32 * 100.0;
+    println!(
+        "Generated LODs for {} meshes: {full} -> {lod1} ({:.0}%) -> {lod2} ({:.0}%) triangles",
+        generated.count,
+        percent(lod1),
+        percent(lod2)
+    );
+    // A reloaded scene is reported on its own
+    *generated = default();
+}
+
+/// The simplified levels of an indexed triangle list and the triangle count of the mesh and each
+/// level, None if neither level simplifies enough
+fn simplify_mesh(mesh: &Mesh) -> Option<([Option<Mesh>; 2], [usize; 3])> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList || mesh.has_morph_targets() {
+        return None;
+    }
+    let indices = mesh.indices()?.iter().map(|i| i as u32).collect::<Vec<_>>();
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let position_bytes = positions
+        .iter()
+        .flatten()
+        .flat_map(|p| p.to_le_bytes())
+        .collect::<Vec<_>>();
+    let vertices = VertexDataAdapter::new(&position_bytes, 12, 0).ok()?;
+
+    let mut levels = [None, None];
+    let mut triangles = [indices.len() / 3; 3];
+    for (i, (share, max_error)) in LOD_TARGETS.into_iter().enumerate() {
+        let target = (indices.len() as f32 * share) as usize / 3 * 3;
+        let mut simplified = meshopt::simplify(&indices, &vertices, target, max_error);
+        let previous = triangles[i];
+        if simplified.is_empty()
+            || simplified.len() / 3 > (previous as f32 * LOD_MIN_REDUCTION) as usize
+        {
+            triangles[i + 1] = previous;
+            continue;
+        }
+        triangles[i + 1] = simplified.len() / 3;
+        // Only the vertices the level still uses are kept
+        let order = first_use_order(&mut simplified, mesh.count_vertices());
+        let mut level = mesh.clone();
+        for (_, values) in level.attributes_mut() {
+            *values = reorder_vertices(values, &order);
+        }
+        let simplified = match mesh.indices() {
+            Some(Indices::U16(_)) => Indices::U16(simplified.iter().map(|i| *i as u16).collect()),
+            _ => Indices::U32(simplified),
+        };
+        level.insert_indices(simplified);
+        levels[i] = Some(level);
+    }
+    if levels.iter().all(Option::is_none) {
+        return None;
+    }
+    Some((levels, triangles))
+}
+
+/// Picks the level of each mesh from its distance to the camera and counts the triangles saved
+#[allow(clippy::type_complexity)]
+fn select_mesh_lods(
+    mut commands: Commands,
+    lods: Res<MeshLods>,
+    camera: Query<&GlobalTransform, (With<Camera>, Without<MinimapCamera>)>,
+    new_entities: Query<(Entity, &Handle<Mesh>), Without<MeshLod>>,
+    mut entities: Query<(
+        Entity,
+        &mut Handle<Mesh>,
+        &mut MeshLod,
+        &GlobalTransform,
+        Option<&Aabb>,
+        &ViewVisibility,
+    )>,
+    mut stats: ResMut<LodStats>,
+) {
+    for (entity, handle) in &new_entities {
+        if let Some(levels) = lods.levels.get(&handle.id()) {
+            commands.entity(entity).insert(mesh_lod(handle, levels));
+        }
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera = camera.translation();
+    *stats = default();
+    for (entity, mut handle, mut lod, transform, aabb, visibility) in &mut entities {
+        // Something else, like instancing, swapped the mesh
+        if *handle != lod.handles[lod.level] {
+            match lods.levels.get(&handle.id()) {
+                Some(levels) => *lod = mesh_lod(&handle, levels),
+                None => {
+                    commands.entity(entity).remove::<MeshLod>();
+                    continue;
+                }
+            }
+        }
+        let Some(aabb) = aabb else {
+            continue;
+        };
+        let center = transform.transform_point(aabb.center.into());
+        let radius = (transform.affine().matrix3 * aabb.half_extents).length();
+        let distance = center.distance(camera) / radius.max(0.01);
+
+        let mut level = lod.level;
+        while level < LOD_DISTANCES.len()
+            && distance > LOD_DISTANCES[level] * (1.0 + LOD_HYSTERESIS)
+        {
+            level += 1;
+        }
+        while level > 0 && distance < LOD_DISTANCES[level - 1] * (1.0 - LOD_HYSTERESIS) {
+            level -= 1;
+        }
+        if level != lod.level {
+            lod.level = level;
+            *handle = lod.handles[level].clone();
+        }
+        if visibility.get() {
+            stats.full += lod.triangles[0];
+            stats.drawn += lod.triangles[lod.level];
+        }
+    }
+}
+
+fn mesh_lod(handle: &Handle<Mesh>, levels: &LodLevels) -> MeshLod {
+    let lod1 = levels.handles[0].clone().unwrap_or_else(|| handle.clone());
+    let lod2 = levels.handles[1].clone().unwrap_or_else(|| lod1.clone());
+    MeshLod {
+        handles: [handle.clone(), lod1, lod2],
+        triangles: levels.triangles,
+        level: 0,
+    }
+}
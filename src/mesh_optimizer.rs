@@ -0,0 +1,179 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+};
+use meshopt::VertexStream;
+
+use crate::benchmark::SceneLoadState;
+
+/// Vertex, index and estimated gpu memory totals of the optimized meshes
+#[derive(Default, Clone, Copy)]
+struct MeshSize {
+    vertices: usize,
+    indices: usize,
+    bytes: usize,
+}
+
+impl MeshSize {
+    fn of(mesh: &Mesh) -> Self {
+        let indices = mesh.indices().map_or(0, Indices::len);
+        let index_size = match mesh.indices() {
+            Some(Indices::U16(_)) => 2,
+            _ => 4,
+        };
+        let vertex_bytes = mesh
+            .attributes()
+            .map(|(_, values)| values.get_bytes().len())
+            .sum::<usize>();
+        Self {
+            vertices: mesh.count_vertices(),
+            indices,
+            bytes: vertex_bytes + indices * index_size,
+        }
+    }
+
+    fn add(&mut self, other: Self) {
+        self.vertices += other.vertices;
+        self.indices += other.indices;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Default)]
+pub struct OptimizedMeshes {
+    count: u32,
+    before: MeshSize,
+    after: MeshSize,
+}
+
+/// With --optimize-meshes, merges duplicate vertices and reorders the triangles and vertices of
+/// each mesh as it's loaded, before it's uploaded, to make better use of the gpu's vertex cache.
+/// The triangles stay the same, so the scene renders identically. Prints the totals before and
+/// after once the scene is loaded.
+pub fn optimize_meshes(
+    mut events: EventReader<AssetEvent<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    scene: SceneLoadState,
+    mut optimized: Local<OptimizedMeshes>,
+) {
+    for event in events.read() {
+        let AssetEvent::Added { id } = event else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(*id) else {
+            continue;
+        };
+        let before = MeshSize::of(mesh);
+        if optimize_mesh(mesh) {
+            optimized.count += 1;
+            optimized.before.add(before);
+            optimized.after.add(MeshSize::of(mesh));
+        }
+    }
+    if optimized.count == 0 || !scene.is_loaded() {
+        return;
+    }
+    let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    let OptimizedMeshes {
+        count,
+        before,
+        after,
+    } = *optimized;
+    println!(
+        "Optimized {count} meshes: {} -> {} vertices, {} -> {} indices, {:.1}MiB -> {:.1}MiB",
+        before.vertices,
+        after.vertices,
+        before.indices,
+        after.indices,
+        mib(before.bytes),
+        mib(after.bytes)
+    );
+    // A reloaded scene is reported on its own
+    *optimized = default();
+}
+
+/// Optimizes an indexed triangle list in place, returns false for meshes it can't handle
+fn optimize_mesh(mesh: &mut Mesh) -> bool {
+    // The morph target texture is indexed by the original vertex order
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList || mesh.has_morph_targets() {
+        return false;
+    }
+    let Some(indices) = mesh.indices() else {
+        return false;
+    };
+    let indices = indices.iter().map(|i| i as u32).collect::<Vec<_>>();
+    let vertex_count = mesh.count_vertices();
+    if indices.is_empty() || vertex_count == 0 {
+        return false;
+    }
+
+    // Vertices with the same value in every attribute are merged
+    let vertex_data = mesh.get_vertex_buffer_data();
+    let stride = vertex_data.len() / vertex_count;
+    let mut stream = VertexStream::new_with_stride::<u8, u8>(vertex_data.as_ptr(), stride);
+    stream.size = stride;
+    let (unique_count, remap) =
+        meshopt::generate_vertex_remap_multi::<u8>(vertex_count, &[stream], Some(&indices));
+    let indices = meshopt::remap_index_buffer(Some(&indices), vertex_count, &remap);
+    let mut indices = meshopt::optimize_vertex_cache(&indices, unique_count);
+    let order = first_use_order(&mut indices, unique_count);
+    // The first original vertex of each merged one
+    let mut source = vec![0; unique_count];
+    for (old, merged) in remap.iter().enumerate().rev() {
+        if (*merged as usize) < unique_count {
+            source[*merged as usize] = old;
+        }
+    }
+    let order = order
+        .iter()
+        .map(|merged| source[*merged])
+        .collect::<Vec<_>>();
+
+    for (_, values) in mesh.attributes_mut() {
+        *values = reorder_vertices(values, &order);
+    }
+    let indices = match mesh.indices() {
+        Some(Indices::U16(_)) => Indices::U16(indices.iter().map(|i| *i as u16).collect()),
+        _ => Indices::U32(indices),
+    };
+    mesh.insert_indices(indices);
+    true
+}
+
+/// Renumbers the vertices by when the triangles first use them, returns the old index of each
+/// used vertex in the new order
+pub fn first_use_order(indices: &mut [u32], vertex_count: usize) -> Vec<usize> {
+    let mut new_index = vec![u32::MAX; vertex_count];
+    let mut order = Vec::with_capacity(vertex_count);
+    for index in indices {
+        let new = &mut new_index[*index as usize];
+        if *new == u32::MAX {
+            *new = order.len() as u32;
+            order.push(*index as usize);
+        }
+        *index = *new;
+    }
+    order
+}
+
+/// The values of the vertices at `order`, in that order
+pub fn reorder_vertices(values: &VertexAttributeValues, order: &[usize]) -> VertexAttributeValues {
+    macro_rules! reorder {
+        ($($variant:ident),*) => {
+            match values {
+                $(VertexAttributeValues::$variant(values) => {
+                    VertexAttributeValues::$variant(order.iter().map(|i| values[*i]).collect())
+                })*
+            }
+        };
+    }
+    reorder!(
+        Float32, Sint32, Uint32, Float32x2, Sint32x2, Uint32x2, Float32x3, Sint32x3, Uint32x3,
+        Float32x4, Sint32x4, Uint32x4, Sint16x2, Snorm16x2, Uint16x2, Unorm16x2, Sint16x4,
+        Snorm16x4, Uint16x4, Unorm16x4, Sint8x2, Snorm8x2, Uint8x2, Unorm8x2, Sint8x4, Snorm8x4,
+        Uint8x4, Unorm8x4
+    )
+}
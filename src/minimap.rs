@@ -0,0 +1,151 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        view::ColorGrading,
+    },
+};
+
+use crate::{
+    camera_controller::CameraController, headless_render_target, scene_bounds::SceneBounds,
+};
+
+// Width and height of the minimap on screen and of its render target, in pixels
+const MINIMAP_SIZE: u32 = 256;
+const MARKER_SIZE: f32 = 8.0;
+// Space left above the scene and around its sides in the top down view, in meters
+const MINIMAP_MARGIN: f32 = 1.0;
+
+/// Renders the scene from above into the minimap image. Queries for the main camera filter it out.
+#[derive(Component)]
+pub struct MinimapCamera;
+
+/// Dot on the minimap where the main camera is
+#[derive(Component)]
+struct MinimapMarker;
+
+/// Top down view of the scene in the bottom right corner with the camera position on it,
+/// enabled with --minimap. It shows up once the [`SceneBounds`] are known.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                fit_minimap.run_if(resource_exists_and_changed::<SceneBounds>),
+                update_minimap,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Spawns the minimap the first time the bounds are computed, after a reload fits it to the new ones
+fn fit_minimap(
+    mut commands: Commands,
+    bounds: Res<SceneBounds>,
+    mut images: ResMut<Assets<Image>>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<MinimapCamera>>,
+) {
+    let size = bounds.size();
+    let extent = size.x.max(size.z) + MINIMAP_MARGIN * 2.0;
+    let center = bounds.center();
+    // North (-Z) is up on the map
+    let transform =
+        Transform::from_translation(Vec3::new(center.x, bounds.max.y + MINIMAP_MARGIN, center.z))
+            .looking_at(center, Vec3::NEG_Z);
+    let projection = Projection::Orthographic(OrthographicProjection {
+        near: 0.0,
+        far: size.y + MINIMAP_MARGIN * 2.0,
+        scaling_mode: ScalingMode::Fixed {
+            width: extent,
+            height: extent,
+        },
+        ..default()
+    });
+
+    if let Ok((mut camera_transform, mut camera_projection)) = camera.get_single_mut() {
+        *camera_transform = transform;
+        *camera_projection = projection;
+        return;
+    }
+    let image = images.add(headless_render_target(MINIMAP_SIZE, MINIMAP_SIZE));
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image.clone()),
+                // Before the main camera, so the minimap is ready when the ui draws it
+                order: -1,
+                ..default()
+            },
+            transform,
+            projection,
+            ..default()
+        },
+        MinimapCamera,
+    ));
+    commands
+        .spawn(ImageBundle {
+            image: UiImage::new(image),
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(8.0),
+                right: Val::Px(8.0),
+                width: Val::Px(MINIMAP_SIZE as f32),
+                height: Val::Px(MINIMAP_SIZE as f32),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|minimap| {
+            minimap.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(MARKER_SIZE),
+                        height: Val::Px(MARKER_SIZE),
+                        ..default()
+                    },
+                    background_color: Color::RED.into(),
+                    ..default()
+                },
+                MinimapMarker,
+            ));
+        });
+}
+
+/// Moves the marker to the main camera and keeps the minimap's exposure the same as the view's
+#[allow(clippy::type_complexity)]
+fn update_minimap(
+    main_camera: Query<(&GlobalTransform, &ColorGrading), With<CameraController>>,
+    mut minimap_camera: Query<
+        (&Transform, &Projection, &mut ColorGrading),
+        (With<MinimapCamera>, Without<CameraController>),
+    >,
+    mut marker: Query<&mut Style, With<MinimapMarker>>,
+) {
+    let (
+        Ok((main_transform, main_grading)),
+        Ok((minimap_transform, projection, mut minimap_grading)),
+        Ok(mut style),
+    ) = (
+        main_camera.get_single(),
+        minimap_camera.get_single_mut(),
+        marker.get_single_mut(),
+    )
+    else {
+        return;
+    };
+    *minimap_grading = *main_grading;
+    let Projection::Orthographic(projection) = projection else {
+        return;
+    };
+    // The camera looks straight down with -Z up, so x and z map straight onto the image
+    let offset = main_transform.translation() - minimap_transform.translation;
+    let half_extent = projection.area.width() * 0.5;
+    let u = ((offset.x + half_extent) / (half_extent * 2.0)).clamp(0.0, 1.0);
+    let v = ((offset.z + half_extent) / (half_extent * 2.0)).clamp(0.0, 1.0);
+    style.left = Val::Px(u * MINIMAP_SIZE as f32 - MARKER_SIZE * 0.5);
+    style.top = Val::Px(v * MINIMAP_SIZE as f32 - MARKER_SIZE * 0.5);
+}
@@ -24,6 +24,10 @@ pub struct MipmapGeneratorSettings {
     pub anisotropic_filtering: u16,
     pub filter_type: FilterType,
     pub minimum_mip_resolution: u32,
+    /// Log progress every time this many images finished generating mips, 0 disables logging.
+    pub progress_log_interval: u32,
+    /// Images larger than this are halved until they fit before their mips are generated.
+    pub max_texture_size: Option<u32>,
 }
 
 ///Mipmaps will not be generated for materials found on entities that also have the `NoMipmapGeneration` component.
@@ -37,6 +41,8 @@ impl Default for MipmapGeneratorSettings {
             anisotropic_filtering: 8,
             filter_type: FilterType::Triangle,
             minimum_mip_resolution: 1,
+            progress_log_interval: 10,
+            max_texture_size: None,
         }
     }
 }
@@ -47,13 +53,23 @@ impl Plugin for MipmapGeneratorPlugin {
         if let Some(image_plugin) = app.get_added_plugins::<ImagePlugin>().first() {
             let default_sampler = image_plugin.default_sampler.clone();
             app.insert_resource(DefaultSampler(default_sampler))
-                .init_resource::<MipmapGeneratorSettings>();
+                .init_resource::<MipmapGeneratorSettings>()
+                .init_resource::<MipmapProgress>();
         } else {
             warn!("No ImagePlugin found. Try adding MipmapGeneratorPlugin after DefaultPlugins");
         }
     }
 }
 
+/// Number of images mipmap generation was started and finished for.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct MipmapProgress {
+    pub total: u32,
+    pub processed: u32,
+    /// Images that were scaled down to fit [`MipmapGeneratorSettings::max_texture_size`]
+    pub downscaled: u32,
+}
+
 #[derive(Resource, Default, Deref, DerefMut)]
 pub struct MipmapTasks<M: Material + GetImages>(HashMap<Handle<Image>, (Task<Image>, Handle<M>)>);
 
@@ -66,6 +82,7 @@ pub fn generate_mipmaps<M: Material + GetImages>(
     mut images: ResMut<Assets<Image>>,
     default_sampler: Res<DefaultSampler>,
     settings: Res<MipmapGeneratorSettings>,
+    mut progress: ResMut<MipmapProgress>,
     mut tasks_res: Option<ResMut<MipmapTasks<M>>>,
 ) {
     let mut new_tasks = MipmapTasks(HashMap::new());
@@ -115,6 +132,7 @@ pub fn generate_mipmaps<M: Material + GetImages>(
                             image
                         });
                         tasks.insert(image_h.clone(), (task, Handle::Weak(*material_h)));
+                        progress.total += 1;
                     }
                 }
             }
@@ -126,12 +144,38 @@ pub fn generate_mipmaps<M: Material + GetImages>(
     for (image_h, inner) in tasks.iter_mut() {
         // TODO couldn't get &mut in destructure to work correctly for (task, material_h)
         if let Some(new_image) = future::block_on(future::poll_once(&mut inner.0)) {
+            let size = new_image.size();
             if let Some(image) = images.get_mut(image_h) {
+                if image.size() != size {
+                    progress.downscaled += 1;
+                }
                 *image = new_image;
             }
             // Touch material to trigger change detection
             let _ = materials.get_mut(&inner.1);
             completed.push(image_h.clone());
+
+            progress.processed += 1;
+            let interval = settings.progress_log_interval;
+            if interval > 0
+                && (progress.processed.is_multiple_of(interval)
+                    || progress.processed == progress.total)
+            {
+                let name = image_h
+                    .path()
+                    .map_or_else(|| format!("{:?}", image_h.id()), |path| path.to_string());
+                info!(
+                    "mipmaps: {}/{} images (last: {name} {}x{})",
+                    progress.processed, progress.total, size.x, size.y
+                );
+            }
+            if progress.processed == progress.total && progress.downscaled > 0 {
+                info!(
+                    "mipmaps: {} images were downscaled to at most {}px",
+                    progress.downscaled,
+                    settings.max_texture_size.unwrap_or_default()
+                );
+            }
         }
     }
 
@@ -151,6 +195,18 @@ pub fn generate_mips_texture(
     check_image_compatible(image)?;
     match try_into_dynamic(image.clone()) {
         Ok(mut dyn_image) => {
+            if let Some(max_size) = settings.max_texture_size {
+                let (mut width, mut height) = (dyn_image.width(), dyn_image.height());
+                while width.max(height) > max_size.max(1) {
+                    width = (width / 2).max(1);
+                    height = (height / 2).max(1);
+                }
+                if width != dyn_image.width() || height != dyn_image.height() {
+                    dyn_image = dyn_image.resize_exact(width, height, settings.filter_type);
+                    image.texture_descriptor.size.width = width;
+                    image.texture_descriptor.size.height = height;
+                }
+            }
             let (mip_level_count, image_data) = generate_mips(
                 &mut dyn_image,
                 settings.minimum_mip_resolution,
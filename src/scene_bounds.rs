@@ -0,0 +1,115 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+
+use crate::{camera_controller::CameraController, minimap::MinimapCamera, Args};
+
+// Direction the camera looks at the scene from with --auto-frame, above and to the side
+const AUTO_FRAME_DIRECTION: Vec3 = Vec3::new(1.0, 0.6, 1.0);
+
+/// Marks the loaded scene, unlike [`crate::PostProcScene`] it stays after the scene is processed
+#[derive(Component)]
+pub struct SceneRoot;
+
+/// World space bounds of all the meshes in the [`SceneRoot`], computed once the scene has spawned
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SceneBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl SceneBounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    fn extend(&mut self, aabb: &Aabb, transform: &GlobalTransform) {
+        let center = transform.transform_point(aabb.center.into());
+        let matrix = transform.affine().matrix3;
+        let half_extents = Vec3::from(
+            matrix.x_axis.abs() * aabb.half_extents.x
+                + matrix.y_axis.abs() * aabb.half_extents.y
+                + matrix.z_axis.abs() * aabb.half_extents.z,
+        );
+        self.min = self.min.min(center - half_extents);
+        self.max = self.max.max(center + half_extents);
+    }
+
+    /// Camera transform that fits the whole bounds in view for a vertical `fov` in radians
+    pub fn framing_transform(&self, fov: f32) -> Transform {
+        let radius = self.size().length() * 0.5;
+        let distance = radius / (fov * 0.5).sin();
+        let center = self.center();
+        Transform::from_translation(center + AUTO_FRAME_DIRECTION.normalize() * distance)
+            .looking_at(center, Vec3::Y)
+    }
+
+    pub fn print(&self) {
+        let (min, max, size) = (self.min, self.max, self.size());
+        println!(
+            "Scene bounds: min ({:.2}, {:.2}, {:.2}), max ({:.2}, {:.2}, {:.2}), size ({:.2}, {:.2}, {:.2})",
+            min.x, min.y, min.z, max.x, max.y, max.z, size.x, size.y, size.z
+        );
+    }
+}
+
+/// Computes [`SceneBounds`] once every mesh in the scene has its [`Aabb`],
+/// and with --auto-frame moves the camera to show the whole scene. A reloaded scene only
+/// gets its bounds updated, the camera stays where it is.
+#[allow(clippy::type_complexity)]
+pub fn compute_scene_bounds(
+    mut commands: Commands,
+    args: Res<Args>,
+    roots: Query<Entity, With<SceneRoot>>,
+    children_query: Query<&Children>,
+    meshes: Query<(Option<&Aabb>, &GlobalTransform), With<Handle<Mesh>>>,
+    mut camera: Query<
+        (&mut Transform, &Projection, Option<&mut CameraController>),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+    mut framed: Local<bool>,
+) {
+    let mut bounds: Option<SceneBounds> = None;
+    for root in &roots {
+        for entity in children_query.iter_descendants(root) {
+            let Ok((aabb, transform)) = meshes.get(entity) else {
+                continue;
+            };
+            // Aabbs are added the frame after the meshes spawn
+            let Some(aabb) = aabb else {
+                return;
+            };
+            bounds
+                .get_or_insert(SceneBounds {
+                    min: Vec3::INFINITY,
+                    max: Vec3::NEG_INFINITY,
+                })
+                .extend(aabb, transform);
+        }
+    }
+    let Some(bounds) = bounds else {
+        return;
+    };
+    bounds.print();
+    commands.insert_resource(bounds);
+
+    if !args.auto_frame || std::mem::replace(&mut *framed, true) {
+        return;
+    }
+    let Ok((mut transform, projection, controller)) = camera.get_single_mut() else {
+        return;
+    };
+    let fov = match projection {
+        Projection::Perspective(perspective) => perspective.fov,
+        Projection::Orthographic(_) => args.fov.to_radians(),
+    };
+    *transform = bounds.framing_transform(fov);
+    if let Some(mut controller) = controller {
+        // Pick up the new yaw/pitch from the transform and orbit around the scene
+        controller.initialized = false;
+        controller.orbit_focus = bounds.center();
+    }
+    println!("Framed the camera on the scene");
+}
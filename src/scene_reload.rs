@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::{
+    key_bindings::KeyBindings,
+    material_overrides::MaterialOverrides,
+    scene_bounds::{SceneBounds, SceneRoot},
+    spawn_scene,
+    watchdog::LoadPhase,
+    Args,
+};
+
+// Warn if the old scene is still loaded this long after despawning it
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The scene that was despawned, waiting for its assets to be freed
+pub struct PendingReload {
+    old_scene: AssetId<Scene>,
+    started: Instant,
+    warned: bool,
+}
+
+/// R: despawn the scene and load it again from disk, keeping the camera, lights and settings.
+/// The new scene is only loaded once the old one is freed, otherwise the asset server would
+/// hand back the old assets instead of reading the file again.
+#[allow(clippy::too_many_arguments)]
+pub fn reload_scene(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    args: Res<Args>,
+    asset_server: Res<AssetServer>,
+    roots: Query<(Entity, &Handle<Scene>), With<SceneRoot>>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    images: Res<Assets<Image>>,
+    mut phase: ResMut<LoadPhase>,
+    mut pending: Local<Option<PendingReload>>,
+) {
+    if let Some(reload) = &mut *pending {
+        if asset_server.get_load_state(reload.old_scene).is_some() {
+            if !reload.warned && reload.started.elapsed() > RELEASE_TIMEOUT {
+                warn!("The old scene is still loaded, something is holding on to its handle");
+                reload.warned = true;
+            }
+            return;
+        }
+        println!(
+            "Released the old scene, {} meshes, {} materials and {} images still loaded",
+            meshes.len(),
+            materials.len(),
+            images.len()
+        );
+        println!("Reloading {}", args.scene);
+        if let Some(path) = &args.material_overrides {
+            match MaterialOverrides::load(path, &args.scene) {
+                Ok(overrides) => commands.insert_resource(overrides),
+                Err(e) => warn!("{e:#}, keeping the previous material overrides"),
+            }
+        }
+        spawn_scene(&mut commands, &asset_server, &args);
+        *phase = LoadPhase::Loading;
+        *pending = None;
+        return;
+    }
+    if !input.just_pressed(keys.reload_scene) {
+        return;
+    }
+    // All the --scene-instances share the same scene
+    let Some((_, scene)) = roots.iter().next() else {
+        return;
+    };
+    println!("Despawning the scene");
+    *pending = Some(PendingReload {
+        old_scene: scene.id(),
+        started: Instant::now(),
+        warned: false,
+    });
+    for (root, _) in &roots {
+        commands.entity(root).despawn_recursive();
+    }
+    // Bounds are computed again for the new scene
+    commands.remove_resource::<SceneBounds>();
+}
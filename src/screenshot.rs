@@ -0,0 +1,304 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    core::FrameCount,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::{
+            BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, Maintain, MapMode,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        view::screenshot::ScreenshotManager,
+        Render, RenderApp, RenderSet,
+    },
+    window::PrimaryWindow,
+};
+use image::{imageops::FilterType, RgbaImage};
+
+use crate::{
+    benchmark::SceneLoadState, headless_render_target, key_bindings::KeyBindings,
+    minimap::MinimapCamera, Args,
+};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+// wgpu requires buffer rows of texture copies to be aligned to this
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+// Frames rendered at the higher resolution before a supersampled capture, so TAA can converge
+const SUPERSAMPLE_SETTLE_FRAMES: u32 = 30;
+
+/// Saves a png of the camera output to [`SCREENSHOT_DIR`].
+/// `position` is the index of the camera position the screenshot was taken at, if any.
+#[derive(Event)]
+pub struct CaptureScreenshot {
+    pub position: Option<usize>,
+}
+
+/// Without a window the camera renders to an image, which is read back in the render world.
+#[derive(Resource, Clone, Default, ExtractResource)]
+struct PendingImageCapture(Option<ImageCapture>);
+
+#[derive(Clone)]
+struct ImageCapture {
+    image: Handle<Image>,
+    path: PathBuf,
+    /// The image is scaled down by this factor before it's saved
+    downsample: u32,
+}
+
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptureScreenshot>()
+            .init_resource::<PendingImageCapture>()
+            .add_plugins(ExtractResourcePlugin::<PendingImageCapture>::default())
+            .add_systems(
+                PostUpdate,
+                (capture_screenshots, supersampled_capture).chain(),
+            );
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_systems(
+                Render,
+                read_back_image_capture
+                    .after(RenderSet::Render)
+                    .before(RenderSet::Cleanup),
+            );
+        }
+    }
+}
+
+fn screenshot_path(frame: u32, position: Option<usize>) -> PathBuf {
+    let position = position.map_or_else(|| String::from("free"), |i| (i + 1).to_string());
+    Path::new(SCREENSHOT_DIR).join(format!("frame_{frame}_pos_{position}.png"))
+}
+
+fn capture_screenshots(
+    mut events: EventReader<CaptureScreenshot>,
+    mut screenshot_manager: ResMut<ScreenshotManager>,
+    mut pending: ResMut<PendingImageCapture>,
+    frame_count: Res<FrameCount>,
+    window: Query<Entity, With<PrimaryWindow>>,
+    cameras: Query<&Camera, Without<MinimapCamera>>,
+) {
+    pending.0 = None;
+    for event in events.read() {
+        let path = screenshot_path(frame_count.0, event.position);
+        if let Err(e) = fs::create_dir_all(SCREENSHOT_DIR) {
+            warn!("Failed to create {SCREENSHOT_DIR}: {e}");
+            return;
+        }
+        if let Ok(window) = window.get_single() {
+            match screenshot_manager.save_screenshot_to_disk(window, &path) {
+                Ok(()) => info!("Saving screenshot {}", path.display()),
+                Err(e) => warn!("{e}"),
+            }
+        } else if let Some(image) = cameras.iter().find_map(|camera| match &camera.target {
+            RenderTarget::Image(image) => Some(image.clone()),
+            _ => None,
+        }) {
+            info!("Saving screenshot {}", path.display());
+            pending.0 = Some(ImageCapture {
+                image,
+                path,
+                downsample: 1,
+            });
+        } else {
+            warn!("Nothing to take a screenshot of");
+        }
+    }
+}
+
+#[derive(Default)]
+enum SupersampleState {
+    #[default]
+    Idle,
+    /// F10 was pressed before the scene finished loading
+    Queued,
+    Rendering {
+        image: Handle<Image>,
+        previous_target: RenderTarget,
+        frames: u32,
+    },
+    /// The capture was read back this frame, the camera goes back to its target next frame
+    Captured {
+        image: Handle<Image>,
+        previous_target: RenderTarget,
+    },
+}
+
+/// F10: renders the camera at --ss-capture times its resolution for a while and saves a
+/// screenshot scaled back down, for stills without aliasing. Waits for the scene to load first.
+#[allow(clippy::too_many_arguments)]
+fn supersampled_capture(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    args: Res<Args>,
+    scene: SceneLoadState,
+    mut cameras: Query<&mut Camera, Without<MinimapCamera>>,
+    mut images: ResMut<Assets<Image>>,
+    mut pending: ResMut<PendingImageCapture>,
+    frame_count: Res<FrameCount>,
+    mut state: Local<SupersampleState>,
+) {
+    let Some(factor) = args.ss_capture else {
+        return;
+    };
+    let Ok(mut camera) = cameras.get_single_mut() else {
+        return;
+    };
+    match &mut *state {
+        SupersampleState::Idle => {
+            if input.just_pressed(keys.supersampled_capture) {
+                if !scene.is_loaded() {
+                    println!("Supersampled capture will start once the scene has loaded");
+                }
+                *state = SupersampleState::Queued;
+            }
+        }
+        SupersampleState::Queued => {
+            if !scene.is_loaded() {
+                return;
+            }
+            let Some(size) = camera.physical_target_size() else {
+                return;
+            };
+            println!(
+                "Rendering a {}x{} supersampled capture",
+                size.x * factor,
+                size.y * factor
+            );
+            let image = images.add(headless_render_target(size.x * factor, size.y * factor));
+            let previous_target =
+                std::mem::replace(&mut camera.target, RenderTarget::Image(image.clone()));
+            *state = SupersampleState::Rendering {
+                image,
+                previous_target,
+                frames: 0,
+            };
+        }
+        SupersampleState::Rendering {
+            image,
+            previous_target,
+            frames,
+        } => {
+            *frames += 1;
+            if *frames < SUPERSAMPLE_SETTLE_FRAMES {
+                return;
+            }
+            if let Err(e) = fs::create_dir_all(SCREENSHOT_DIR) {
+                warn!("Failed to create {SCREENSHOT_DIR}: {e}");
+            }
+            let path =
+                Path::new(SCREENSHOT_DIR).join(format!("frame_{}_ss_{factor}x.png", frame_count.0));
+            info!("Saving screenshot {}", path.display());
+            pending.0 = Some(ImageCapture {
+                image: image.clone(),
+                path,
+                downsample: factor,
+            });
+            *state = SupersampleState::Captured {
+                image: image.clone(),
+                previous_target: previous_target.clone(),
+            };
+        }
+        SupersampleState::Captured {
+            image,
+            previous_target,
+        } => {
+            camera.target = previous_target.clone();
+            images.remove(&*image);
+            *state = SupersampleState::Idle;
+        }
+    }
+}
+
+fn read_back_image_capture(
+    pending: Res<PendingImageCapture>,
+    images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(ImageCapture {
+        image,
+        path,
+        downsample,
+    }) = &pending.0
+    else {
+        return;
+    };
+    let Some(gpu_image) = images.get(image) else {
+        warn!("Screenshot image isn't ready");
+        return;
+    };
+    let width = gpu_image.size.x as u32;
+    let height = gpu_image.size.y as u32;
+    let bytes_per_pixel = gpu_image.texture_format.block_copy_size(None).unwrap_or(4);
+    let row_bytes = width * bytes_per_pixel;
+    let padded_row_bytes =
+        row_bytes.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("screenshot_readback_buffer"),
+        size: (padded_row_bytes * height) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("screenshot_readback"),
+    });
+    encoder.copy_texture_to_buffer(
+        gpu_image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    render_device.map_buffer(&slice, MapMode::Read, |result| {
+        if let Err(e) = result {
+            warn!("Failed to map screenshot buffer: {e}");
+        }
+    });
+    render_device.poll(Maintain::Wait);
+
+    let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+    for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+        pixels.extend_from_slice(&row[..row_bytes as usize]);
+    }
+    buffer.unmap();
+
+    let Some(mut capture) = RgbaImage::from_raw(width, height, pixels) else {
+        warn!("Screenshot readback has the wrong size");
+        return;
+    };
+    if *downsample > 1 {
+        capture = image::imageops::resize(
+            &capture,
+            width / downsample,
+            height / downsample,
+            FilterType::Lanczos3,
+        );
+    }
+    if let Err(e) = capture.save(path) {
+        warn!("Failed to save screenshot {}: {e}", path.display());
+    }
+}
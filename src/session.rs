@@ -0,0 +1,101 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use bevy::{
+    app::AppExit,
+    core_pipeline::{
+        bloom::BloomSettings, experimental::taa::TemporalAntiAliasSettings, fxaa::Fxaa,
+    },
+    pbr::ScreenSpaceAmbientOcclusionSettings,
+    prelude::*,
+    render::view::ColorGrading,
+};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{exposure_mut, minimap::MinimapCamera, Args};
+
+/// Which post effects are on the camera
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PostEffects {
+    pub bloom: bool,
+    pub ssao: bool,
+    pub anti_aliasing: bool,
+    pub environment_map: bool,
+}
+
+impl PostEffects {
+    pub fn all(enabled: bool) -> Self {
+        Self {
+            bloom: enabled,
+            ssao: enabled,
+            anti_aliasing: enabled,
+            environment_map: enabled,
+        }
+    }
+}
+
+/// Camera state saved on exit and restored on startup with `--session <path>`
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct Session {
+    pub camera: Transform,
+    pub exposure: f32,
+    pub effects: PostEffects,
+}
+
+impl Session {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read session {}", path.display()))?;
+        ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse session {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let contents = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn save_session_on_exit(
+    mut exit: EventReader<AppExit>,
+    args: Res<Args>,
+    mut camera: Query<
+        (
+            &Transform,
+            &mut ColorGrading,
+            Has<BloomSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+            Has<Fxaa>,
+            Has<EnvironmentMapLight>,
+        ),
+        (With<Camera>, Without<MinimapCamera>),
+    >,
+) {
+    if exit.read().last().is_none() {
+        return;
+    }
+    let (Some(path), Ok((transform, mut color_grading, bloom, ssao, taa, fxaa, env_map))) =
+        (&args.session, camera.get_single_mut())
+    else {
+        return;
+    };
+    let session = Session {
+        camera: *transform,
+        exposure: *exposure_mut(&mut color_grading),
+        effects: PostEffects {
+            bloom,
+            ssao,
+            anti_aliasing: taa || fxaa,
+            environment_map: env_map,
+        },
+    };
+    match session.save(path) {
+        Ok(()) => println!("Saved session to {path}"),
+        Err(e) => eprintln!("Failed to save session: {e:#}"),
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::render_resource::TextureFormat,
+    utils::{HashMap, HashSet},
+};
+use serde::Serialize;
+
+use crate::{key_bindings::KeyBindings, Args, PostProcScene};
+
+/// Approximate size of an image with a full mip chain on the gpu, in bytes
+pub fn estimate_image_vram(image: &Image) -> u64 {
+    let size = image.texture_descriptor.size;
+    let format = image.texture_descriptor.format;
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+    let blocks_x = size.width.div_ceil(block_width) as u64;
+    let blocks_y = size.height.div_ceil(block_height) as u64;
+    let base = blocks_x * blocks_y * block_size * size.depth_or_array_layers as u64;
+    // A full mip chain adds about a third
+    base * 4 / 3
+}
+
+/// K: print a breakdown of the loaded materials and textures
+pub fn material_stats(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    materials: Res<Assets<StandardMaterial>>,
+    images: Res<Assets<Image>>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+) {
+    if !input.just_pressed(keys.material_stats) {
+        return;
+    }
+
+    let mut transmissive = 0;
+    let mut double_sided = 0;
+    for (_, mat) in materials.iter() {
+        if mat.diffuse_transmission > 0.0 || mat.specular_transmission > 0.0 {
+            transmissive += 1;
+        }
+        if mat.double_sided {
+            double_sided += 1;
+        }
+    }
+    let transmissive_entities = has_std_mat
+        .iter()
+        .filter(|mat_h| {
+            materials
+                .get(*mat_h)
+                .is_some_and(|mat| mat.diffuse_transmission > 0.0)
+        })
+        .count();
+
+    let mut formats: HashMap<TextureFormat, (u32, u64)> = HashMap::new();
+    let mut total_vram = 0;
+    for (_, image) in images.iter() {
+        let vram = estimate_image_vram(image);
+        let entry = formats.entry(image.texture_descriptor.format).or_default();
+        entry.0 += 1;
+        entry.1 += vram;
+        total_vram += vram;
+    }
+    let mut formats = formats.into_iter().collect::<Vec<_>>();
+    formats.sort_by_key(|(_, (_, vram))| std::cmp::Reverse(*vram));
+
+    println!("Materials: {}", materials.len());
+    println!("    with transmission: {transmissive} (used by {transmissive_entities} entities)");
+    println!("    double sided: {double_sided}");
+    println!("Images: {}", images.len());
+    for (format, (count, vram)) in formats {
+        println!(
+            "    {format:?}: {count} images, {:.1}MiB",
+            vram as f64 / (1024.0 * 1024.0)
+        );
+    }
+    println!(
+        "Estimated texture VRAM: {:.1}MiB",
+        total_vram as f64 / (1024.0 * 1024.0)
+    );
+}
+
+/// J: print how many draw calls instancing or merging could save.
+/// Entities with the same mesh and material could be drawn with one instanced draw,
+/// entities with the same material could be merged into one mesh.
+pub fn draw_call_stats(
+    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<KeyBindings>,
+    has_mesh: Query<(&Handle<Mesh>, &Handle<StandardMaterial>)>,
+) {
+    if !input.just_pressed(keys.draw_call_stats) {
+        return;
+    }
+
+    let mut groups: HashMap<(AssetId<Mesh>, AssetId<StandardMaterial>), u32> = HashMap::new();
+    let mut materials = HashSet::new();
+    let mut entities = 0;
+    for (mesh_h, mat_h) in &has_mesh {
+        *groups.entry((mesh_h.id(), mat_h.id())).or_default() += 1;
+        materials.insert(mat_h.id());
+        entities += 1;
+    }
+    let mut shared = groups
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .collect::<Vec<_>>();
+    shared.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+
+    println!("Mesh entities (draw calls without batching): {entities}");
+    println!(
+        "    unique mesh + material pairs: {}, {} could be saved by instancing",
+        groups.len(),
+        entities - groups.len()
+    );
+    println!(
+        "    unique materials: {}, {} could be saved by merging meshes per material",
+        materials.len(),
+        entities - materials.len()
+    );
+    println!("    pairs used more than once: {}", shared.len());
+    for ((mesh, material), count) in shared.iter().take(10) {
+        println!("        {count}x mesh {mesh:?} material {material:?}");
+    }
+}
+
+/// The fields of a [`StandardMaterial`] that the gltf or [`crate::proc_scene`] set
+#[derive(Serialize)]
+struct MaterialReport {
+    name: String,
+    base_color: [f32; 4],
+    alpha_mode: String,
+    diffuse_transmission: f32,
+    specular_transmission: f32,
+    thickness: f32,
+    double_sided: bool,
+    base_color_texture: Option<String>,
+    normal_map_texture: Option<String>,
+    metallic_roughness_texture: Option<String>,
+    emissive_texture: Option<String>,
+    occlusion_texture: Option<String>,
+}
+
+fn asset_name<A: Asset>(handle: &Handle<A>) -> String {
+    handle
+        .path()
+        .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string())
+}
+
+impl MaterialReport {
+    fn new(handle: &Handle<StandardMaterial>, material: &StandardMaterial) -> Self {
+        let texture = |image: &Option<Handle<Image>>| image.as_ref().map(asset_name);
+        Self {
+            name: asset_name(handle),
+            base_color: material.base_color.as_linear_rgba_f32(),
+            alpha_mode: format!("{:?}", material.alpha_mode),
+            diffuse_transmission: material.diffuse_transmission,
+            specular_transmission: material.specular_transmission,
+            thickness: material.thickness,
+            double_sided: material.double_sided,
+            base_color_texture: texture(&material.base_color_texture),
+            normal_map_texture: texture(&material.normal_map_texture),
+            metallic_roughness_texture: texture(&material.metallic_roughness_texture),
+            emissive_texture: texture(&material.emissive_texture),
+            occlusion_texture: texture(&material.occlusion_texture),
+        }
+    }
+}
+
+fn write_material_reports(path: &Path, reports: &[MaterialReport]) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(reports)?;
+    fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Writes the materials used by the scene to --dump-materials as json once [`crate::proc_scene`]
+/// has processed them, then exits.
+pub fn dump_materials(
+    args: Res<Args>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut done: Local<bool>,
+) {
+    let Some(path) = &args.dump_materials else {
+        return;
+    };
+    if *done || !loading_scenes.is_empty() || has_std_mat.is_empty() {
+        return;
+    }
+    *done = true;
+
+    let mut seen = HashSet::new();
+    let mut reports = has_std_mat
+        .iter()
+        .filter(|mat_h| seen.insert(mat_h.id()))
+        .filter_map(|mat_h| Some(MaterialReport::new(mat_h, materials.get(mat_h)?)))
+        .collect::<Vec<_>>();
+    reports.sort_by(|a, b| a.name.cmp(&b.name));
+    match write_material_reports(Path::new(path), &reports) {
+        Ok(()) => println!("Wrote {} materials to {path}", reports.len()),
+        Err(e) => eprintln!("Failed to dump materials: {e:#}"),
+    }
+    app_exit.send(AppExit);
+}
+
+/// Values in `material` that render wrong, e.g. NaN colors that come out black
+fn material_problems(material: &StandardMaterial) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut check_color = |name: &str, color: Color| {
+        let rgba = color.as_linear_rgba_f32();
+        if rgba.iter().any(|c| !c.is_finite()) {
+            problems.push(format!("{name} {rgba:?} isn't finite"));
+        } else if rgba.iter().any(|c| *c < 0.0) {
+            problems.push(format!("{name} {rgba:?} is negative"));
+        }
+    };
+    check_color("base_color", material.base_color);
+    check_color("emissive", material.emissive);
+    check_color("attenuation_color", material.attenuation_color);
+
+    let unit_values = [
+        ("perceptual_roughness", material.perceptual_roughness),
+        ("metallic", material.metallic),
+        ("reflectance", material.reflectance),
+        ("diffuse_transmission", material.diffuse_transmission),
+        ("specular_transmission", material.specular_transmission),
+    ];
+    for (name, value) in unit_values {
+        if !(0.0..=1.0).contains(&value) {
+            problems.push(format!("{name} {value} is outside 0..=1"));
+        }
+    }
+    if !material.thickness.is_finite() || material.thickness < 0.0 {
+        problems.push(format!("thickness {} is invalid", material.thickness));
+    }
+    if !material.ior.is_finite() || material.ior < 1.0 {
+        problems.push(format!("ior {} is below 1 or invalid", material.ior));
+    }
+    // Infinite is the default, no attenuation
+    if material.attenuation_distance.is_nan() || material.attenuation_distance <= 0.0 {
+        problems.push(format!(
+            "attenuation_distance {} isn't positive",
+            material.attenuation_distance
+        ));
+    }
+    if let AlphaMode::Mask(cutoff) = material.alpha_mode {
+        if !(0.0..=1.0).contains(&cutoff) {
+            problems.push(format!("alpha cutoff {cutoff} is outside 0..=1"));
+        }
+    }
+    problems
+}
+
+/// With --check-materials, once [`crate::proc_scene`] has processed the scene, lists the
+/// materials with NaN or out of range values and exits, with an error if any were found.
+pub fn check_materials(
+    loading_scenes: Query<(), With<PostProcScene>>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut done: Local<bool>,
+) {
+    if *done || !loading_scenes.is_empty() || has_std_mat.is_empty() {
+        return;
+    }
+    *done = true;
+
+    let mut seen = HashSet::new();
+    let mut invalid = has_std_mat
+        .iter()
+        .filter(|mat_h| seen.insert(mat_h.id()))
+        .filter_map(|mat_h| {
+            let problems = material_problems(materials.get(mat_h)?);
+            (!problems.is_empty()).then(|| (asset_name(mat_h), problems))
+        })
+        .collect::<Vec<_>>();
+    invalid.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, problems) in &invalid {
+        println!("{name}:");
+        for problem in problems {
+            println!("    {problem}");
+        }
+    }
+    if !invalid.is_empty() {
+        eprintln!(
+            "{} of {} materials have invalid values",
+            invalid.len(),
+            seen.len()
+        );
+        std::process::exit(1);
+    }
+    println!("All {} materials are valid", seen.len());
+    app_exit.send(AppExit);
+}
@@ -0,0 +1,201 @@
+use std::collections::BinaryHeap;
+
+use bevy::{prelude::*, render::render_asset::RenderAssetUsages, utils::HashMap};
+
+use crate::{
+    benchmark::SceneLoadState, mipmap_generator::GetImages, scene_bounds::SceneRoot, Args,
+};
+
+/// The loaded images kept out of the render world until the budget is applied, with the usages
+/// they are given back
+#[derive(Resource, Default)]
+pub struct HeldTextures {
+    usages: HashMap<AssetId<Image>, RenderAssetUsages>,
+    /// The scene root the budget was last applied to
+    applied_to: Option<Entity>,
+}
+
+/// While the scene loads, takes the images loaded from files out of the render world before
+/// they are extracted, so no texture is uploaded at full size before the budget is applied. Runs
+/// in `Last`, after the asset events of the frame are sent and before the render world reads them.
+pub fn hold_textures(
+    mut events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    roots: Query<Entity, With<SceneRoot>>,
+    mut held: ResMut<HeldTextures>,
+) {
+    if roots.iter().min() == held.applied_to {
+        events.clear();
+        return;
+    }
+    for event in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        // Mipmap generation replaces the image with a copy that may still be uploadable
+        let Some(usage) = images.get(*id).map(|image| image.asset_usage) else {
+            continue;
+        };
+        if !usage.contains(RenderAssetUsages::RENDER_WORLD) || asset_server.get_path(*id).is_none()
+        {
+            continue;
+        }
+        held.usages.entry(*id).or_insert(usage);
+        if let Some(image) = images.get_mut(*id) {
+            image.asset_usage = RenderAssetUsages::MAIN_WORLD;
+        }
+    }
+}
+
+/// Once the scene and its mipmaps are loaded, drops the top mip levels of the largest material
+/// textures until they all fit in --texture-budget-mb, then hands the held textures to the render
+/// world. Runs once per scene, again after a reload.
+pub fn apply_texture_budget(
+    args: Res<Args>,
+    scene: SceneLoadState,
+    roots: Query<Entity, With<SceneRoot>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut held: ResMut<HeldTextures>,
+) {
+    let Some(budget_mb) = args.texture_budget_mb else {
+        return;
+    };
+    // Any of the --scene-instances, they are all replaced on reload
+    let Some(root) = roots.iter().min() else {
+        return;
+    };
+    if held.applied_to == Some(root) || !scene.is_loaded() {
+        return;
+    }
+    held.applied_to = Some(root);
+    fit_to_budget(budget_mb, &mut materials, &mut images);
+    // Uploaded at their final size
+    for (id, usage) in held.usages.drain() {
+        if let Some(image) = images.get_mut(id) {
+            image.asset_usage = usage;
+        }
+    }
+}
+
+fn fit_to_budget(
+    budget_mb: u32,
+    materials: &mut Assets<StandardMaterial>,
+    images: &mut Assets<Image>,
+) {
+    let budget = budget_mb as u64 * 1024 * 1024;
+
+    // The materials using each texture
+    let mut users: HashMap<Handle<Image>, Vec<AssetId<StandardMaterial>>> = HashMap::new();
+    for (id, material) in materials.iter() {
+        for handle in material.get_images() {
+            users.entry(handle.clone()).or_default().push(id);
+        }
+    }
+    let mut total = 0;
+    // Largest first, (size, mips dropped so far, handle)
+    let mut largest = BinaryHeap::new();
+    for handle in users.keys().cloned() {
+        if let Some(image) = images.get(&handle) {
+            let size = image.data.len() as u64;
+            total += size;
+            largest.push((size, 0, handle));
+        }
+    }
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    if total <= budget {
+        println!(
+            "Textures use {:.1}MiB, within the {budget_mb}MiB budget",
+            mib(total)
+        );
+        return;
+    }
+
+    let mut dropped = Vec::new();
+    while total > budget {
+        let Some((size, levels, handle)) = largest.pop() else {
+            break;
+        };
+        let Some(image) = images.get(&handle) else {
+            continue;
+        };
+        let Some(smaller) = top_mip_dropped(image, levels + 1) else {
+            // Only one mip left or a layout we can't split, keep the rest of it
+            if levels > 0 {
+                dropped.push((handle, levels));
+            }
+            continue;
+        };
+        total = total - size + smaller;
+        largest.push((smaller, levels + 1, handle));
+    }
+    dropped.extend(
+        largest
+            .into_iter()
+            .filter(|(_, levels, _)| *levels > 0)
+            .map(|(_, levels, handle)| (handle, levels)),
+    );
+
+    for (handle, levels) in &dropped {
+        let Some(image) = images.get_mut(handle) else {
+            continue;
+        };
+        let before = image.size();
+        for _ in 0..*levels {
+            drop_top_mip(image);
+        }
+        // Touch the materials so their bind groups pick up the new texture
+        for id in &users[handle] {
+            let _ = materials.get_mut(*id);
+        }
+        let name = handle
+            .path()
+            .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string());
+        println!(
+            "Downscaled {name} from {}x{} to {}x{}",
+            before.x,
+            before.y,
+            image.width(),
+            image.height()
+        );
+    }
+    println!(
+        "Downscaled {} textures to fit the {budget_mb}MiB budget, textures now use {:.1}MiB",
+        dropped.len(),
+        mib(total)
+    );
+    if total > budget {
+        warn!("Textures are still over the budget, they can't be downscaled any further");
+    }
+}
+
+/// Bytes in mip `level` of `image`
+fn mip_size(image: &Image, level: u32) -> u64 {
+    let descriptor = &image.texture_descriptor;
+    let (block_width, block_height) = descriptor.format.block_dimensions();
+    let block_size = descriptor.format.block_copy_size(None).unwrap_or(4) as u64;
+    let width = (descriptor.size.width >> level).max(1);
+    let height = (descriptor.size.height >> level).max(1);
+    width.div_ceil(block_width) as u64 * height.div_ceil(block_height) as u64 * block_size
+}
+
+/// Size of `image` with its top `levels` mips dropped, `None` if it would have no mips left
+/// or it has more than one layer, which interleaves the mips.
+fn top_mip_dropped(image: &Image, levels: u32) -> Option<u64> {
+    let descriptor = &image.texture_descriptor;
+    if levels >= descriptor.mip_level_count || descriptor.size.depth_or_array_layers != 1 {
+        return None;
+    }
+    let dropped = (0..levels).map(|level| mip_size(image, level)).sum::<u64>();
+    Some(image.data.len() as u64 - dropped)
+}
+
+fn drop_top_mip(image: &mut Image) {
+    let top = mip_size(image, 0) as usize;
+    image.data.drain(..top);
+    let descriptor = &mut image.texture_descriptor;
+    descriptor.mip_level_count -= 1;
+    descriptor.size.width = (descriptor.size.width / 2).max(1);
+    descriptor.size.height = (descriptor.size.height / 2).max(1);
+}
@@ -0,0 +1,193 @@
+use anyhow::{anyhow, bail, Context};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::convert::{find_source_images, is_normal_map, ktx2_path, normal_map_stems};
+
+/// Images decoding to a lower PSNR than this (in dB) against their source are flagged
+const MIN_PSNR: f64 = 30.0;
+
+const KTX1_IDENTIFIER: [u8; 12] = *b"\xABKTX 11\xBB\r\n\x1A\n";
+const KTX2_IDENTIFIER: [u8; 12] = *b"\xABKTX 20\xBB\r\n\x1A\n";
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+const GL_RGBA: u32 = 0x1908;
+
+/// Difference between a converted texture and its source
+struct ImageError {
+    psnr: f64,
+    mean_abs_error: f64,
+}
+
+/// Decodes the ktx2 textures in `dst` that `--convert` made from the png textures in `src`
+/// and compares them with the originals. Errors if any are missing or below [`MIN_PSNR`].
+pub fn validate_ktx2_images(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    let images = find_source_images(src)?;
+    let normal_maps = normal_map_stems(src)?;
+    let total = images.len();
+    let mut flagged = 0;
+    for (i, path) in images.iter().enumerate() {
+        let ktx2 = ktx2_path(path, dst);
+        let name = path.file_name().unwrap().to_string_lossy();
+        match compare_ktx2_to_source(path, &ktx2, is_normal_map(path, &normal_maps)) {
+            Ok(error) => {
+                let low = error.psnr < MIN_PSNR;
+                println!(
+                    "{}/{total} {name}: PSNR {:.1}dB, mean abs error {:.2}{}",
+                    i + 1,
+                    error.psnr,
+                    error.mean_abs_error,
+                    if low { " LOW" } else { "" }
+                );
+                if low {
+                    flagged += 1;
+                }
+            }
+            Err(e) => {
+                println!("{}/{total} {name}: FAILED {e:#}", i + 1);
+                flagged += 1;
+            }
+        }
+    }
+    if flagged > 0 {
+        return Err(anyhow!(
+            "{flagged} of {total} images failed validation (PSNR below {MIN_PSNR}dB or unreadable)"
+        ));
+    }
+    println!("All {total} images are above {MIN_PSNR}dB PSNR");
+    Ok(())
+}
+
+fn compare_ktx2_to_source(
+    source: &Path,
+    ktx2: &Path,
+    normal_map: bool,
+) -> anyhow::Result<ImageError> {
+    if !ktx2.exists() {
+        bail!("{} is missing", ktx2.display());
+    }
+    let source_image =
+        image::open(source).with_context(|| format!("Failed to open {}", source.display()))?;
+    let has_alpha = source_image.color().has_alpha();
+    let source_image = source_image.to_rgba8();
+    let (width, height, decoded) = decode_ktx2(ktx2)?;
+    if (width, height) != source_image.dimensions() {
+        bail!(
+            "size is {width}x{height} but the source is {}x{}",
+            source_image.width(),
+            source_image.height()
+        );
+    }
+
+    // Normal maps only keep two channels, alpha only matters if the source has it
+    let channels = if normal_map {
+        2
+    } else if has_alpha {
+        4
+    } else {
+        3
+    };
+    let mut squared_error = 0.0;
+    let mut abs_error = 0.0;
+    for (a, b) in source_image
+        .as_raw()
+        .chunks_exact(4)
+        .zip(decoded.chunks_exact(4))
+    {
+        for c in 0..channels {
+            let diff = a[c] as f64 - b[c] as f64;
+            squared_error += diff * diff;
+            abs_error += diff.abs();
+        }
+    }
+    let samples = (width as u64 * height as u64 * channels as u64) as f64;
+    let mse = squared_error / samples;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+    Ok(ImageError {
+        psnr,
+        mean_abs_error: abs_error / samples,
+    })
+}
+
+/// Decodes the first mip of `path` to rgba8 with kram
+fn decode_ktx2(path: &Path) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let decoded_path = decoded_path(path);
+    let output = Command::new("kram")
+        .arg("decode")
+        .arg("-i")
+        .arg(path)
+        .arg("-o")
+        .arg(&decoded_path)
+        .output()
+        .context("Failed to start kram")?;
+    if !output.status.success() {
+        bail!(
+            "kram decode failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let data = fs::read(&decoded_path);
+    let _ = fs::remove_file(&decoded_path);
+    read_rgba8_ktx(&data?)
+}
+
+fn decoded_path(path: &Path) -> PathBuf {
+    let name = path.file_stem().unwrap().to_string_lossy();
+    std::env::temp_dir().join(format!("{name}_{}_validate.ktx", std::process::id()))
+}
+
+/// Reads the first mip of an uncompressed rgba8 ktx or ktx2 file
+fn read_rgba8_ktx(data: &[u8]) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    if data.starts_with(&KTX2_IDENTIFIER) {
+        let reader = ktx2::Reader::new(data).map_err(|e| anyhow!("invalid ktx2: {e:?}"))?;
+        let header = reader.header();
+        if !matches!(
+            header.format,
+            Some(ktx2::Format::R8G8B8A8_UNORM | ktx2::Format::R8G8B8A8_SRGB)
+        ) || header.supercompression_scheme.is_some()
+        {
+            bail!("kram decoded to {:?}, expected rgba8", header.format);
+        }
+        let level = reader.levels().next().context("decoded ktx2 has no mips")?;
+        return Ok((header.pixel_width, header.pixel_height, level.to_vec()));
+    }
+    if !data.starts_with(&KTX1_IDENTIFIER) {
+        bail!("kram didn't write a ktx file");
+    }
+    let field = |i: usize| -> anyhow::Result<u32> {
+        let offset = KTX1_IDENTIFIER.len() + i * 4;
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .context("truncated ktx header")
+    };
+    // Header fields after the identifier: endianness, glType, glTypeSize, glFormat,
+    // glInternalFormat, glBaseInternalFormat, width, height, depth, array elements,
+    // faces, mips, key/value bytes
+    if field(0)? != 0x04030201 {
+        bail!("big endian ktx files aren't supported");
+    }
+    if field(1)? != GL_UNSIGNED_BYTE || field(3)? != GL_RGBA {
+        bail!("kram decoded to an unexpected format, expected rgba8");
+    }
+    let (width, height) = (field(6)?, field(7)?);
+    let image_size_offset = KTX1_IDENTIFIER.len() + 13 * 4 + field(12)? as usize;
+    let image_size = data
+        .get(image_size_offset..image_size_offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .context("truncated ktx file")? as usize;
+    let start = image_size_offset + 4;
+    let pixels = data
+        .get(start..start + image_size)
+        .context("truncated ktx file")?;
+    if pixels.len() != (width * height * 4) as usize {
+        bail!("first mip has {} bytes, expected rgba8", pixels.len());
+    }
+    Ok((width, height, pixels.to_vec()))
+}
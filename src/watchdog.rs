@@ -0,0 +1,97 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::mipmap_generator::MipmapProgress;
+
+// How often the watchdog thread checks on the frames
+const WATCHDOG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the app is busy with, set by [`crate::setup`] and [`crate::proc_scene`].
+/// Generating mipmaps is read from [`MipmapProgress`].
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadPhase {
+    Loading,
+    GeneratingMipmaps,
+    Rendering,
+}
+
+impl fmt::Display for LoadPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LoadPhase::Loading => "loading",
+            LoadPhase::GeneratingMipmaps => "generating mipmaps",
+            LoadPhase::Rendering => "rendering",
+        })
+    }
+}
+
+struct Heartbeat {
+    last_frame: Instant,
+    phase: LoadPhase,
+}
+
+/// With --watchdog, a thread that warns when no frame has completed for a while, with the
+/// last phase the app was in, and exits with --watchdog-exit. It can't run on the main thread
+/// since that is what hangs.
+#[derive(Resource)]
+pub struct Watchdog(Arc<Mutex<Heartbeat>>);
+
+impl Watchdog {
+    pub fn start(timeout: Duration, exit: bool) -> Self {
+        let heartbeat = Arc::new(Mutex::new(Heartbeat {
+            last_frame: Instant::now(),
+            phase: LoadPhase::Loading,
+        }));
+        let watched = heartbeat.clone();
+        std::thread::spawn(move || {
+            let mut stalled = false;
+            loop {
+                std::thread::sleep(WATCHDOG_POLL_INTERVAL);
+                let (since_frame, phase) = {
+                    let heartbeat = watched.lock().unwrap();
+                    (heartbeat.last_frame.elapsed(), heartbeat.phase)
+                };
+                if since_frame < timeout {
+                    if stalled {
+                        info!("Frames are completing again");
+                        stalled = false;
+                    }
+                    continue;
+                }
+                if !stalled {
+                    warn!(
+                        "No frame completed for {:.0}s, last phase: {phase}",
+                        since_frame.as_secs_f32()
+                    );
+                    stalled = true;
+                }
+                if exit {
+                    eprintln!("Exiting, the render stalled while {phase}");
+                    std::process::exit(1);
+                }
+            }
+        });
+        Self(heartbeat)
+    }
+}
+
+/// Tells the watchdog a frame completed, at the end of each frame
+pub fn watchdog_heartbeat(
+    watchdog: Res<Watchdog>,
+    phase: Res<LoadPhase>,
+    mipmaps: Res<MipmapProgress>,
+) {
+    let phase = if mipmaps.processed < mipmaps.total {
+        LoadPhase::GeneratingMipmaps
+    } else {
+        *phase
+    };
+    let mut heartbeat = watchdog.0.lock().unwrap();
+    heartbeat.last_frame = Instant::now();
+    heartbeat.phase = phase;
+}